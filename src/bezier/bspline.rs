@@ -0,0 +1,422 @@
+use bevy::prelude::{Vec2, Vec3, Vec4};
+use std::ops::{Add, Mul};
+use Vec3 as Color;
+
+use crate::bezier::dyn_rectangle::DynBezierRectangle;
+use crate::bezier::rectangle::{self, NetFormat};
+use crate::error::ParseError;
+use crate::math;
+use crate::tokens::{self, Token, TokenStream};
+
+/*
+    A tensor product B-spline surface of degree_u in u and degree_v in v direction
+    The count_u * count_v control points are laid out row by row like the net of a BezierRectangle,
+    the knot vectors have count + degree + 1 values each
+    The surface is not drawn itself, it is split into one bezier patch per knot span by knot insertion.
+    The colors, alphas and texture coordinates of the corners are spread over the patches.
+    Rational surfaces have Vec4 control points in homogeneous coordinates, the knots are inserted in 4D
+*/
+#[derive(Debug, Clone)]
+pub struct BSplineSurface<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    points: Vec<T>,
+    degree_u: usize,
+    degree_v: usize,
+    knots_u: Vec<f32>,
+    knots_v: Vec<f32>,
+    colors: [Color; 4],
+    alphas: [f32; 4],
+    uvs: [Vec2; 4],
+}
+
+impl<T> BSplineSurface<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T> + std::fmt::Debug,
+{
+    //the knot vectors have to be valid, see check_knots
+    pub fn new(
+        points: Vec<T>,
+        (degree_u, degree_v): (usize, usize),
+        knots_u: Vec<f32>,
+        knots_v: Vec<f32>,
+        colors: [Color; 4],
+    ) -> Self {
+        let (count_u, count_v) = (knots_u.len() - degree_u - 1, knots_v.len() - degree_v - 1);
+        assert_eq!(points.len(), count_u * count_v);
+        BSplineSurface {
+            points: points,
+            degree_u: degree_u,
+            degree_v: degree_v,
+            knots_u: knots_u,
+            knots_v: knots_v,
+            colors: colors,
+            alphas: [1.; 4],
+            uvs: rectangle::CORNERS,
+        }
+    }
+
+    pub fn with_alphas(mut self, alphas: [f32; 4]) -> Self {
+        self.alphas = alphas;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [Vec2; 4]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    /*
+        splits the surface into its bezier patches, ordered by span in v and within that by span in u
+        first every row is converted to the bezier points of its spans, then every column of the result,
+        neighbouring patches share the control points of their common edge
+    */
+    pub fn to_patches(&self) -> Vec<DynBezierRectangle<T>> {
+        let (p, q) = (self.degree_u, self.degree_v);
+        let count_u = self.knots_u.len() - p - 1;
+        let rows: Vec<Vec<T>> = self
+            .points
+            .chunks_exact(count_u)
+            .map(|row| bezier_points(row, &self.knots_u, p))
+            .collect();
+        let width = rows[0].len();
+        let columns: Vec<Vec<T>> = (0..width)
+            .map(|i| {
+                let column: Vec<T> = rows.iter().map(|row| row[i]).collect();
+                bezier_points(&column, &self.knots_v, q)
+            })
+            .collect();
+
+        let breaks_u = normalized_breakpoints(&self.knots_u, p);
+        let breaks_v = normalized_breakpoints(&self.knots_v, q);
+        let mut patches = Vec::new();
+        for sv in 0..breaks_v.len() - 1 {
+            for su in 0..breaks_u.len() - 1 {
+                let mut points = Vec::with_capacity((p + 1) * (q + 1));
+                for j in 0..q + 1 {
+                    for i in 0..p + 1 {
+                        points.push(columns[su * p + i][sv * q + j]);
+                    }
+                }
+                //the corners of the patch in the parameters of the whole surface, scaled to [0,1]
+                let corners = rectangle::CORNERS.map(|c| {
+                    let u = math::lerp(breaks_u[su], breaks_u[su + 1], c.x);
+                    let v = math::lerp(breaks_v[sv], breaks_v[sv + 1], c.y);
+                    Vec2::new(u, v)
+                });
+                let colors = corners.map(|c| corner_bilerp(&self.colors, c));
+                let alphas = corners.map(|c| corner_bilerp(&self.alphas, c));
+                let uvs = corners.map(|c| corner_bilerp(&self.uvs, c));
+                patches.push(
+                    DynBezierRectangle::new(points, p, q, colors)
+                        .with_alphas(alphas)
+                        .with_uvs(uvs),
+                );
+            }
+        }
+        patches
+    }
+}
+
+impl BSplineSurface<Vec3> {
+    //reads a surface of the given degrees, see read_surface
+    pub fn read(
+        tokens: &mut TokenStream,
+        degree_u: usize,
+        degree_v: usize,
+        format: NetFormat,
+        default_color: Color,
+    ) -> Result<Self, ParseError> {
+        let format = NetFormat {
+            rational: false,
+            ..format
+        };
+        let surface = read_surface(tokens, degree_u, degree_v, format, default_color)?;
+        Ok(BSplineSurface {
+            points: surface.points.into_iter().map(math::project).collect(),
+            degree_u: surface.degree_u,
+            degree_v: surface.degree_v,
+            knots_u: surface.knots_u,
+            knots_v: surface.knots_v,
+            colors: surface.colors,
+            alphas: surface.alphas,
+            uvs: surface.uvs,
+        })
+    }
+}
+
+impl BSplineSurface<Vec4> {
+    //reads a rational surface of the given degrees, whose control points have four coordinates
+    pub fn read_rational(
+        tokens: &mut TokenStream,
+        degree_u: usize,
+        degree_v: usize,
+        format: NetFormat,
+        default_color: Color,
+    ) -> Result<Self, ParseError> {
+        let format = NetFormat {
+            rational: true,
+            ..format
+        };
+        read_surface(tokens, degree_u, degree_v, format, default_color)
+    }
+}
+
+/*
+    reads the number of control points in u and v direction, the knot vectors in u and in v direction
+    and the control points with their texture coordinates and colors as in rectangle::read_net
+*/
+fn read_surface(
+    tokens: &mut TokenStream,
+    degree_u: usize,
+    degree_v: usize,
+    format: NetFormat,
+    default_color: Color,
+) -> Result<BSplineSurface<Vec4>, ParseError> {
+    let count_tokens = tokens.take(2, "control point count")?;
+    let counts = tokens::parse_all::<usize>(&count_tokens, "control point count")?;
+    let (count_u, count_v) = (counts[0], counts[1]);
+    for (count, degree, t) in [
+        (count_u, degree_u, &count_tokens[0]),
+        (count_v, degree_v, &count_tokens[1]),
+    ] {
+        if count <= degree {
+            let reason = format!(
+                "a surface of degree {} needs at least {} control points",
+                degree,
+                degree + 1
+            );
+            return Err(ParseError::new(t.line, t.column, reason));
+        }
+    }
+    //the counts come from the file, a net with more points than can be addressed is an error and not an overflow
+    let num_points = count_u
+        .checked_mul(count_v)
+        .filter(|n| n.checked_mul(4).is_some())
+        .ok_or_else(|| {
+            let t = &count_tokens[0];
+            ParseError::new(
+                t.line,
+                t.column,
+                format!("grid size too large: {} x {}", count_u, count_v),
+            )
+        })?;
+    let knot_tokens_u = tokens.take(count_u + degree_u + 1, "knot")?;
+    let knot_tokens_v = tokens.take(count_v + degree_v + 1, "knot")?;
+    let (points, uvs, colors, alphas) = rectangle::read_net(tokens, num_points, format, default_color)?;
+
+    let knots_u = read_knots(&knot_tokens_u, degree_u)?;
+    let knots_v = read_knots(&knot_tokens_v, degree_v)?;
+    Ok(
+        BSplineSurface::new(points, (degree_u, degree_v), knots_u, knots_v, colors)
+            .with_alphas(alphas)
+            .with_uvs(uvs.unwrap_or(rectangle::CORNERS)),
+    )
+}
+
+//parses a knot vector and checks it, errors are reported at its first knot
+fn read_knots(knots: &[Token], degree: usize) -> Result<Vec<f32>, ParseError> {
+    let values = tokens::parse_all::<f32>(knots, "knot")?;
+    match check_knots(&values, degree) {
+        Some(reason) => Err(ParseError::new(knots[0].line, knots[0].column, reason)),
+        None => Ok(values),
+    }
+}
+
+/*
+    the reason why a knot vector can not be used, or None if it can
+    the knots have to be non-decreasing, the domain [knots[degree], knots[count]] must not be empty
+    and no knot may be repeated more than degree + 1 times, which would leave the surface undefined there
+*/
+pub fn check_knots(knots: &[f32], degree: usize) -> Option<String> {
+    if knots.windows(2).any(|w| w[0] > w[1]) {
+        return Some(String::from("the knots have to be non-decreasing"));
+    }
+    let count = knots.len() - degree - 1;
+    if knots[degree] == knots[count] {
+        return Some(format!(
+            "the domain [{}, {}] of the knots is empty",
+            knots[degree], knots[count]
+        ));
+    }
+    knots
+        .iter()
+        .find(|u| multiplicity(knots, **u) > degree + 1)
+        .map(|u| format!("knot {} is repeated more than {} times", u, degree + 1))
+}
+
+fn multiplicity(knots: &[f32], u: f32) -> usize {
+    knots.iter().filter(|k| **k == u).count()
+}
+
+//the distinct knots in the domain of a curve with the given knots, ie. the ends of its spans
+fn breakpoints(knots: &[f32], degree: usize) -> Vec<f32> {
+    let count = knots.len() - degree - 1;
+    let mut breaks = knots[degree..count + 1].to_vec();
+    breaks.dedup();
+    breaks
+}
+
+//the breakpoints scaled to [0,1]
+fn normalized_breakpoints(knots: &[f32], degree: usize) -> Vec<f32> {
+    let breaks = breakpoints(knots, degree);
+    let (start, end) = (breaks[0], breaks[breaks.len() - 1]);
+    breaks.iter().map(|u| (u - start) / (end - start)).collect()
+}
+
+//value at c of the bilinear interpolation of corner values, which are ordered like rectangle::CORNERS
+fn corner_bilerp<C>(values: &[C; 4], c: Vec2) -> C
+where
+    C: Copy + Add<C, Output = C> + Mul<f32, Output = C>,
+{
+    math::bilerp(values[0], values[2], values[1], values[3], c.x, c.y)
+}
+
+/*
+    inserts the knot u into a B-spline curve of the given degree, whose domain contains u (Boehm's algorithm)
+    only the degree control points before the span of u change, they are replaced by degree + 1 new ones
+*/
+fn insert_knot<T>(points: &mut Vec<T>, knots: &mut Vec<f32>, degree: usize, u: f32)
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let n = points.len();
+    //the span [knots[k], knots[k + 1]] that contains u, the last span of the domain for its end
+    let k = (degree..n).rev().find(|k| knots[*k] <= u).unwrap();
+    let mut inserted = Vec::with_capacity(n + 1);
+    for i in 0..n + 1 {
+        if i + degree <= k {
+            inserted.push(points[i]);
+        } else if i > k {
+            inserted.push(points[i - 1]);
+        } else {
+            let a = (u - knots[i]) / (knots[i + degree] - knots[i]);
+            inserted.push(math::lerp(points[i - 1], points[i], a));
+        }
+    }
+    knots.insert(k + 1, u);
+    *points = inserted;
+}
+
+/*
+    the control points of the bezier curves that a B-spline curve consists of, one for each span of its domain
+    every breakpoint is inserted until it is repeated degree times, then the degree + 1 control points
+    before the end of each span are the points of its bezier curve
+    consecutive curves share their end point, which is only returned once, so there are spans * degree + 1 points
+*/
+fn bezier_points<T>(points: &[T], knots: &[f32], degree: usize) -> Vec<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let (mut points, mut knots) = (points.to_vec(), knots.to_vec());
+    let breaks = breakpoints(&knots, degree);
+    for u in &breaks {
+        while multiplicity(&knots, *u) < degree {
+            insert_knot(&mut points, &mut knots, degree, *u);
+        }
+    }
+    let mut bezier = vec![];
+    for (s, u) in breaks[..breaks.len() - 1].iter().enumerate() {
+        let k = knots.iter().rposition(|knot| knot == u).unwrap();
+        let segment = &points[k - degree..k + 1];
+        if s == 0 {
+            bezier.push(segment[0]);
+        }
+        bezier.extend_from_slice(&segment[1..]);
+    }
+    bezier
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //evaluates the B-spline curve at u with the de boor algorithm
+    fn de_boor(points: &[f32], knots: &[f32], degree: usize, u: f32) -> f32 {
+        let k = (degree..points.len()).rev().find(|k| knots[*k] <= u).unwrap();
+        let mut d: Vec<f32> = points[k - degree..k + 1].to_vec();
+        for r in 1..degree + 1 {
+            for j in (r..degree + 1).rev() {
+                let i = k - degree + j;
+                let a = (u - knots[i]) / (knots[i + degree + 1 - r] - knots[i]);
+                d[j] = math::lerp(d[j - 1], d[j], a);
+            }
+        }
+        d[degree]
+    }
+
+    fn bezier_evaluate(points: &[f32], t: f32) -> f32 {
+        let triangle = math::compute_triangular_scheme(points, t);
+        triangle[triangle.len() - 1]
+    }
+
+    #[test]
+    fn bezier_decomposition() {
+        //a clamped cubic with two interior knots, one of them double, and an unclamped quadratic
+        let cases: [(&[f32], &[f32], usize); 2] = [
+            (&[0., 2., -1., 3., 1., 4.], &[0., 0., 0., 0., 1., 1., 3., 3., 3., 3.], 3),
+            (&[1., 0., 2., 5.], &[0., 1., 2., 3., 4., 5., 6.], 2),
+        ];
+        for (points, knots, degree) in cases {
+            let bezier = bezier_points(points, knots, degree);
+            let breaks = breakpoints(knots, degree);
+            assert_eq!(bezier.len(), (breaks.len() - 1) * degree + 1);
+            for s in 0..breaks.len() - 1 {
+                let segment = &bezier[s * degree..(s + 1) * degree + 1];
+                for t in [0., 0.25, 0.5, 1.] {
+                    let u = math::lerp(breaks[s], breaks[s + 1], t);
+                    let expected = de_boor(points, knots, degree, u.min(breaks[s + 1]));
+                    assert!((bezier_evaluate(segment, t) - expected).abs() < 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn knot_checks() {
+        assert_eq!(check_knots(&[0., 0., 1., 1.], 1), None);
+        assert!(check_knots(&[0., 1., 0.5, 1.], 1).is_some());
+        assert!(check_knots(&[0., 1., 1., 1.], 1).is_some());
+        assert!(check_knots(&[0., 0., 0., 1., 1.], 1).is_some());
+    }
+
+    #[test]
+    fn parse_bspline_surface() {
+        //a biquadratic surface with one interior knot in u, so it consists of two patches
+        let mut input = String::from("4 3\n0 0 0 0.5 1 1 1\n0 0 0 1 1 1\n");
+        for j in 0..3 {
+            for i in 0..4 {
+                input += &format!("{} {} {}\n", i, j, (i * j) % 2);
+            }
+        }
+        input += "1 0 0\n0 1 0\n0 0 1\n1 1 1 0.5\n";
+        let colored = NetFormat {
+            colored: true,
+            ..NetFormat::default()
+        };
+        let mut tokens = TokenStream::new(&input);
+        let surface = BSplineSurface::read(&mut tokens, 2, 2, colored, Color::ONE).unwrap();
+        let patches = surface.to_patches();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[0].degree(), (2, 2));
+        //the patches meet at u = 0.5 of the surface
+        for v in [0., 0.3, 1.] {
+            assert!(patches[0].evaluate(1., v).distance(patches[1].evaluate(0., v)) < 1e-5);
+        }
+        assert_eq!(patches[0].evaluate(0., 0.), Vec3::ZERO);
+        assert_eq!(patches[1].evaluate(1., 1.), Vec3::new(3., 2., 0.));
+
+        let broken = input.replacen("0.5", "2", 1);
+        let e = BSplineSurface::read(&mut TokenStream::new(&broken), 2, 2, colored, Color::ONE).unwrap_err();
+        assert_eq!((e.line, e.column), (2, 1));
+        assert_eq!(e.reason, "the knots have to be non-decreasing");
+        let e = BSplineSurface::read(&mut TokenStream::new(&input), 4, 2, colored, Color::ONE).unwrap_err();
+        assert_eq!((e.line, e.column), (1, 1));
+        assert_eq!(e.reason, "a surface of degree 4 needs at least 5 control points");
+        let huge = "4294967296 4294967296 0 0 0";
+        let e = BSplineSurface::read(&mut TokenStream::new(huge), 2, 2, colored, Color::ONE).unwrap_err();
+        assert_eq!((e.line, e.column), (1, 1));
+        assert_eq!(e.reason, "grid size too large: 4294967296 x 4294967296");
+    }
+}
@@ -1,33 +1,199 @@
+use bevy::prelude::{Mat4, Vec3};
 use std::ops::{Add, Mul};
+use Vec3 as Color;
 
+use crate::math::{self, Transform};
+use crate::segment::{Segment, ToSegments};
+use crate::subdivision::{Flatness, Subdivide};
+
+//a bezier curve of degree N with a color at each end, which is interpolated along the curve
+#[derive(Debug, Clone)]
 pub struct BezierCurve<T, const N: usize>
 where
     T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
-    [(); N + 1]:,
 {
-    points: [T; N + 1],
+    points: Vec<T>,
+    colors: [Color; 2],
+    alphas: [f32; 2],
 }
 
 impl<T, const N: usize> BezierCurve<T, N>
 where
     T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
-    [(); N + 1]:,
 {
-    pub fn new(points: [T; N + 1]) -> Self {
-        BezierCurve { points: points }
+    //P is the number of control points, which has to be N+1
+    pub fn new<const P: usize>(points: [T; P]) -> Self {
+        const { assert!(P == N + 1, "wrong number of control points") };
+        BezierCurve {
+            points: points.to_vec(),
+            colors: [Color::ONE; 2],
+            alphas: [1.; 2],
+        }
+    }
+
+    //a curve of degree N from the control points of a curve of lower degree, which describe the same curve
+    pub fn elevated(points: &[T]) -> Self {
+        assert!(
+            points.len() > 0 && points.len() <= N + 1,
+            "wrong number of control points"
+        );
+        BezierCurve {
+            points: elevate(points, N),
+            colors: [Color::ONE; 2],
+            alphas: [1.; 2],
+        }
+    }
+
+    pub fn with_colors(mut self, colors: [Color; 2]) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub fn with_alphas(mut self, alphas: [f32; 2]) -> Self {
+        self.alphas = alphas;
+        self
+    }
+
+    pub fn points(&self) -> &[T] {
+        &self.points
+    }
+
+    pub fn colors(&self) -> [Color; 2] {
+        self.colors
+    }
+
+    pub fn alphas(&self) -> [f32; 2] {
+        self.alphas
     }
-    fn evaluate(&self, t: f32) -> T {
-        unimplemented!()
+
+    pub fn evaluate(&self, t: f32) -> T {
+        let triangle = math::compute_triangular_scheme(&self.points, t);
+        triangle[triangle.len() - 1]
     }
-    fn subdivide(&self, t: f32) -> (Self, Self) {
-        unimplemented!()
+
+    pub fn evaluate_with_derivative(&self, t: f32) -> (T, T) {
+        let triangle = math::compute_triangular_scheme(&self.points, t);
+        math::point_and_derivative(&triangle, N)
+    }
+
+    pub fn subdivide(&self, t: f32) -> (Self, Self) {
+        let (first, second) = split_points(&self.points, t);
+        let (colors_first, colors_second) = split_ends(self.colors, t);
+        let (alphas_first, alphas_second) = split_ends(self.alphas, t);
+        let a = BezierCurve {
+            points: first,
+            colors: colors_first,
+            alphas: alphas_first,
+        };
+        let b = BezierCurve {
+            points: second,
+            colors: colors_second,
+            alphas: alphas_second,
+        };
+        (a, b)
+    }
+}
+
+/*
+    the control points of a curve of higher degree that describe the same curve,
+    each elevation step replaces the n+1 points b_i by the n+2 points i/(n+1) b_i-1 + (1 - i/(n+1)) b_i
+*/
+pub fn elevate<T>(points: &[T], degree: usize) -> Vec<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let mut points = points.to_vec();
+    while points.len() < degree + 1 {
+        let n = points.len() as f32;
+        let mut elevated = vec![points[0]];
+        for i in 1..points.len() {
+            let s = i as f32 / n;
+            elevated.push(points[i - 1] * s + points[i] * (1. - s));
+        }
+        elevated.push(points[points.len() - 1]);
+        points = elevated;
+    }
+    points
+}
+
+/*
+    the control points of the two halves of a curve split at t, they are the left and the right edge of the triangle,
+    row r of the triangle starts at the sum of the lengths of the rows before it
+*/
+pub fn split_points<T>(points: &[T], t: f32) -> (Vec<T>, Vec<T>)
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let triangle = math::compute_triangular_scheme(points, t);
+    let mut first = Vec::with_capacity(points.len());
+    let mut second = Vec::with_capacity(points.len());
+    let mut row_offset = 0;
+    for row in 0..points.len() {
+        let row_len = points.len() - row;
+        first.push(triangle[row_offset]);
+        second.push(triangle[row_offset + row_len - 1]);
+        row_offset += row_len;
+    }
+    second.reverse();
+    (first, second)
+}
+
+//the values at the ends of the two halves of a curve split at t, eg. its colors
+pub fn split_ends<T>(ends: [T; 2], t: f32) -> ([T; 2], [T; 2])
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let split = math::lerp(ends[0], ends[1], t);
+    ([ends[0], split], [split, ends[1]])
+}
+
+//distance of the control points from the line between the end points
+pub fn points_flatness(points: &[Vec3]) -> f32 {
+    let (start, end) = (points[0], points[points.len() - 1]);
+    let degree = (points.len() - 1).max(1) as f32;
+    let mut distance = 0f32;
+    for (i, p) in points.iter().enumerate() {
+        let flat = math::lerp(start, end, i as f32 / degree);
+        distance = distance.max(p.distance(flat));
+    }
+    distance
+}
+
+impl<T, const N: usize> Subdivide for BezierCurve<T, N>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    fn subdivide(&self) -> Vec<Self> {
+        let (a, b) = BezierCurve::subdivide(self, 0.5);
+        vec![a, b]
+    }
+}
+
+impl<const N: usize> Transform for BezierCurve<Vec3, N> {
+    fn transform(&mut self, m: Mat4) {
+        for p in &mut self.points {
+            *p = m.project_point3(*p);
+        }
+    }
+}
+
+impl<const N: usize> Flatness for BezierCurve<Vec3, N> {
+    fn flatness(&self) -> f32 {
+        points_flatness(&self.points)
+    }
+}
+
+//a curve is drawn as the line between its end points, so it should be subdivided first
+impl<const N: usize> ToSegments for BezierCurve<Vec3, N> {
+    fn to_segments(&self) -> Vec<Segment> {
+        let segment = Segment::new([self.points[0], self.points[N]], self.colors);
+        vec![segment.with_alphas(self.alphas)]
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bevy::prelude::Vec3;
 
     #[test]
     fn initialization() {
@@ -40,4 +206,47 @@ mod tests {
         let b2 = BezierCurve::<_, 2>::new(pts2);
         let b3 = BezierCurve::<_, 0>::new([3.]);
     }
+
+    #[test]
+    fn bezier_curve_evaluate() {
+        //the parabola y = x^2 on [0, 1]
+        let b = BezierCurve::<_, 2>::new([Vec3::ZERO, Vec3::new(0.5, 0., 0.), Vec3::new(1., 1., 0.)]);
+        assert_eq!(b.evaluate(0.), Vec3::ZERO);
+        assert_eq!(b.evaluate(1.), Vec3::new(1., 1., 0.));
+        assert_eq!(b.evaluate(0.5), Vec3::new(0.5, 0.25, 0.));
+        let (point, derivative) = b.evaluate_with_derivative(0.5);
+        assert_eq!(point, Vec3::new(0.5, 0.25, 0.));
+        assert_eq!(derivative, Vec3::new(1., 1., 0.));
+        assert_eq!(BezierCurve::<_, 0>::new([3.]).evaluate(0.7), 3.);
+    }
+
+    #[test]
+    fn bezier_curve_subdivide() {
+        let b = BezierCurve::<_, 3>::new([0., 1., 3., 2.]);
+        let (l, r) = b.subdivide(0.25);
+        assert_eq!(l.points()[0], b.evaluate(0.));
+        assert_eq!(l.points()[3], b.evaluate(0.25));
+        assert_eq!(r.points()[0], b.evaluate(0.25));
+        assert_eq!(r.points()[3], b.evaluate(1.));
+        for t in [0., 0.3, 0.5, 1.] {
+            assert!((l.evaluate(t) - b.evaluate(t * 0.25)).abs() < 1e-6);
+            assert!((r.evaluate(t) - b.evaluate(0.25 + t * 0.75)).abs() < 1e-6);
+        }
+        let colored = BezierCurve::<Vec3, 1>::new([Vec3::ZERO, Vec3::X]).with_colors([Color::X, Color::Y]);
+        let halves = colored.subdivide(0.5);
+        assert_eq!(halves.0.colors, [Color::X, Color::new(0.5, 0.5, 0.)]);
+        assert_eq!(halves.1.colors, [Color::new(0.5, 0.5, 0.), Color::Y]);
+    }
+
+    #[test]
+    fn degree_elevation() {
+        let quadratic = BezierCurve::<_, 2>::new([Vec3::ZERO, Vec3::new(0.5, 1., 0.), Vec3::new(1., 0., 2.)]);
+        let elevated = BezierCurve::<Vec3, 9>::elevated(quadratic.points());
+        assert_eq!(elevated.points().len(), 10);
+        for t in [0., 0.2, 0.5, 0.9, 1.] {
+            assert!(elevated.evaluate(t).distance(quadratic.evaluate(t)) < 1e-5);
+        }
+        let line = BezierCurve::<Vec3, 3>::elevated(&[Vec3::ZERO, Vec3::X]);
+        assert!(line.flatness() < 1e-6);
+    }
 }
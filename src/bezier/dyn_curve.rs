@@ -0,0 +1,194 @@
+use bevy::prelude::{Mat4, Vec3};
+use std::ops::{Add, Mul};
+use Vec3 as Color;
+
+use crate::bezier::curve::{self, BezierCurve};
+use crate::error::ParseError;
+use crate::math::{self, Transform};
+use crate::segment::{Segment, ToSegments};
+use crate::subdivision::{Flatness, Subdivide};
+use crate::tokens::{self, TokenStream};
+
+/*
+    A bezier curve whose degree is only known at runtime, eg. because it was read from a file
+    It behaves exactly like a BezierCurve of the degree given by its number of control points,
+    the algorithms are shared with it, see curve::split_points
+*/
+#[derive(Debug, Clone)]
+pub struct DynBezierCurve<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    points: Vec<T>,
+    colors: [Color; 2],
+    alphas: [f32; 2],
+}
+
+impl<T> DynBezierCurve<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    //a curve of degree points.len() - 1
+    pub fn new(points: Vec<T>) -> Self {
+        assert!(!points.is_empty(), "a curve needs at least one control point");
+        DynBezierCurve {
+            points: points,
+            colors: [Color::ONE; 2],
+            alphas: [1.; 2],
+        }
+    }
+
+    pub fn with_colors(mut self, colors: [Color; 2]) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub fn with_alphas(mut self, alphas: [f32; 2]) -> Self {
+        self.alphas = alphas;
+        self
+    }
+
+    pub fn degree(&self) -> usize {
+        self.points.len() - 1
+    }
+
+    pub fn points(&self) -> &[T] {
+        &self.points
+    }
+
+    pub fn evaluate(&self, t: f32) -> T {
+        let triangle = math::compute_triangular_scheme(&self.points, t);
+        triangle[triangle.len() - 1]
+    }
+
+    pub fn evaluate_with_derivative(&self, t: f32) -> (T, T) {
+        let triangle = math::compute_triangular_scheme(&self.points, t);
+        math::point_and_derivative(&triangle, self.degree())
+    }
+
+    pub fn subdivide(&self, t: f32) -> (Self, Self) {
+        let (first, second) = curve::split_points(&self.points, t);
+        let (colors_first, colors_second) = curve::split_ends(self.colors, t);
+        let (alphas_first, alphas_second) = curve::split_ends(self.alphas, t);
+        let a = DynBezierCurve {
+            points: first,
+            colors: colors_first,
+            alphas: alphas_first,
+        };
+        let b = DynBezierCurve {
+            points: second,
+            colors: colors_second,
+            alphas: alphas_second,
+        };
+        (a, b)
+    }
+}
+
+impl<T, const N: usize> From<BezierCurve<T, N>> for DynBezierCurve<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    fn from(curve: BezierCurve<T, N>) -> Self {
+        DynBezierCurve {
+            points: curve.points().to_vec(),
+            colors: curve.colors(),
+            alphas: curve.alphas(),
+        }
+    }
+}
+
+impl DynBezierCurve<Vec3> {
+    /*
+        reads the degree + 1 control points of a curve followed by the colors of its start and its end if it is colored,
+        uncolored curves get the default color
+        colors are given as rgba, the alpha value is optional if it would be on the next line
+    */
+    pub fn read(
+        tokens: &mut TokenStream,
+        degree: usize,
+        colored: bool,
+        default_color: Color,
+    ) -> Result<Self, ParseError> {
+        let points = tokens.take(3 * (degree + 1), "coordinate")?;
+        let mut color_values = Vec::new();
+        for _ in 0..if colored { 2 } else { 0 } {
+            let mut values = tokens.take(3, "color component")?;
+            values.extend(tokens.take_rest_of_line(1));
+            color_values.push(values);
+        }
+        let points = tokens::parse_vec3s(&points, "coordinate")?;
+        let mut colors = [default_color; 2];
+        let mut alphas = [1.; 2];
+        for (i, color) in tokens::parse_colors(&color_values)?.into_iter().enumerate() {
+            colors[i] = color.truncate();
+            alphas[i] = color.w;
+        }
+        Ok(DynBezierCurve::new(points).with_colors(colors).with_alphas(alphas))
+    }
+}
+
+impl<T> Subdivide for DynBezierCurve<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    fn subdivide(&self) -> Vec<Self> {
+        let (a, b) = DynBezierCurve::subdivide(self, 0.5);
+        vec![a, b]
+    }
+}
+
+impl Transform for DynBezierCurve<Vec3> {
+    fn transform(&mut self, m: Mat4) {
+        for p in &mut self.points {
+            *p = m.project_point3(*p);
+        }
+    }
+}
+
+impl Flatness for DynBezierCurve<Vec3> {
+    fn flatness(&self) -> f32 {
+        curve::points_flatness(&self.points)
+    }
+}
+
+//see BezierCurve::to_segments
+impl ToSegments for DynBezierCurve<Vec3> {
+    fn to_segments(&self) -> Vec<Segment> {
+        let segment = Segment::new([self.points[0], self.points[self.degree()]], self.colors);
+        vec![segment.with_alphas(self.alphas)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_typed_curve() {
+        let typed = BezierCurve::<_, 3>::new([Vec3::ZERO, Vec3::Y, Vec3::new(1., 1., 2.), Vec3::X]);
+        let curve = DynBezierCurve::from(typed.clone());
+        assert_eq!(curve.degree(), 3);
+        for t in [0., 0.3, 0.5, 1.] {
+            assert_eq!(curve.evaluate(t), typed.evaluate(t));
+            assert_eq!(curve.evaluate_with_derivative(t), typed.evaluate_with_derivative(t));
+        }
+        let (l, r) = curve.subdivide(0.4);
+        let (typed_l, typed_r) = typed.subdivide(0.4);
+        assert_eq!(l.points(), typed_l.points());
+        assert_eq!(r.points(), typed_r.points());
+        assert_eq!(curve.flatness(), typed.flatness());
+    }
+
+    #[test]
+    fn parse_bezier_curve() {
+        let mut tokens = TokenStream::new("0 0 0  1 1 0  2 0 0\n1 0 0\n0 0 1 0.5\n0 0 0 1 1 1");
+        let curve = DynBezierCurve::read(&mut tokens, 2, true, Color::ONE).unwrap();
+        //the curve keeps the degree it was given with
+        assert_eq!(curve.degree(), 2);
+        assert!(curve.evaluate(0.5).distance(Vec3::new(1., 0.5, 0.)) < 1e-6);
+        assert_eq!(curve.colors, [Color::X, Color::Z]);
+        assert_eq!(curve.alphas, [1., 0.5]);
+        let e = DynBezierCurve::read(&mut tokens, 1, true, Color::ONE).unwrap_err();
+        assert_eq!(e.reason, "missing color component, 3 values expected but 0 found");
+    }
+}
@@ -0,0 +1,465 @@
+use bevy::prelude::{Mat4, Vec2, Vec3, Vec4};
+use std::ops::{Add, Mul};
+use Vec3 as Color;
+
+use crate::bezier::rectangle::{self, BezierRectangle, NetFormat};
+use crate::error::ParseError;
+use crate::math::{self, Transform};
+use crate::stitching::Surface;
+use crate::subdivision::{Flatness, Subdivide};
+use crate::tokens::TokenStream;
+use crate::triangle::{ToTriangle, Triangle};
+
+/*
+    A rectangular bezier patch whose degrees are only known at runtime
+    It behaves exactly like a BezierRectangle of degree_u in u and degree_v in v direction,
+    the control points, colors, alphas, texture coordinates and domain are laid out the same way
+    the builder uses it for the patches of all degrees read from a file
+    and for rational patches, whose control points are Vec4 in homogeneous coordinates.
+    These are subdivided in 4D and only projected when they are evaluated
+*/
+#[derive(Debug, Clone)]
+pub struct DynBezierRectangle<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    points: Vec<T>,
+    degree_u: usize,
+    degree_v: usize,
+    colors: [Color; 4],
+    alphas: [f32; 4],
+    uvs: [Vec2; 4],
+    domain: [Vec2; 2],
+}
+
+impl<T> DynBezierRectangle<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T> + std::fmt::Debug,
+{
+    pub fn new(points: Vec<T>, degree_u: usize, degree_v: usize, colors: [Color; 4]) -> Self {
+        assert_eq!(points.len(), (degree_u + 1) * (degree_v + 1));
+        DynBezierRectangle {
+            points: points,
+            degree_u: degree_u,
+            degree_v: degree_v,
+            colors: colors,
+            alphas: [1.; 4],
+            uvs: rectangle::CORNERS,
+            domain: [Vec2::ZERO, Vec2::ONE],
+        }
+    }
+
+    //see BezierRectangle::with_alphas
+    pub fn with_alphas(mut self, alphas: [f32; 4]) -> Self {
+        self.alphas = alphas;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [Vec2; 4]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    pub fn degree(&self) -> (usize, usize) {
+        (self.degree_u, self.degree_v)
+    }
+
+    pub fn domain(&self) -> [Vec2; 2] {
+        self.domain
+    }
+
+    //control points along the given side, ordered by increasing parameter
+    pub fn boundary(&self, side: math::Side2D) -> Vec<T> {
+        rectangle::net_boundary(&self.points, self.degree_u, self.degree_v, side)
+    }
+
+    pub fn evaluate(&self, u: f32, v: f32) -> T {
+        self.evaluate_with_derivatives(u, v).0
+    }
+
+    //see BezierRectangle::evaluate_with_derivatives
+    pub fn evaluate_with_derivatives(&self, u: f32, v: f32) -> (T, T, T) {
+        let (point, du, dv, _) = self.evaluate_with_mixed_derivative(u, v);
+        (point, du, dv)
+    }
+
+    fn evaluate_with_mixed_derivative(&self, u: f32, v: f32) -> (T, T, T, T) {
+        rectangle::net_evaluate(&self.points, self.degree_u, self.degree_v, u, v)
+    }
+
+    pub fn subdivide_cross(&self) -> Vec<Self> {
+        let (l, r) = self.subdivide(math::Axis2D::U, 0.5);
+        let (tl, bl) = l.subdivide(math::Axis2D::V, 0.5);
+        let (tr, br) = r.subdivide(math::Axis2D::V, 0.5);
+
+        vec![tl, bl, tr, br]
+    }
+
+    pub fn subdivide(&self, axis: math::Axis2D, t: f32) -> (Self, Self) {
+        let (n, m) = (self.degree_u, self.degree_v);
+        let (first, second) = rectangle::net_subdivide(&self.points, n, m, axis, t);
+        let (colors_first, colors_second) = rectangle::split_colors(&self.colors, axis, t);
+        let (alphas_first, alphas_second) = rectangle::split_colors(&self.alphas, axis, t);
+        let (uvs_first, uvs_second) = rectangle::split_colors(&self.uvs, axis, t);
+        let (domain_first, domain_second) = rectangle::split_domain(&self.domain, axis, t);
+        (
+            DynBezierRectangle {
+                points: first,
+                degree_u: n,
+                degree_v: m,
+                colors: colors_first,
+                alphas: alphas_first,
+                uvs: uvs_first,
+                domain: domain_first,
+            },
+            DynBezierRectangle {
+                points: second,
+                degree_u: n,
+                degree_v: m,
+                colors: colors_second,
+                alphas: alphas_second,
+                uvs: uvs_second,
+                domain: domain_second,
+            },
+        )
+    }
+}
+
+impl<T, const N: usize, const M: usize> From<BezierRectangle<T, N, M>> for DynBezierRectangle<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T> + std::fmt::Debug,
+{
+    fn from(patch: BezierRectangle<T, N, M>) -> Self {
+        DynBezierRectangle {
+            points: patch.points().to_vec(),
+            degree_u: N,
+            degree_v: M,
+            colors: patch.colors(),
+            alphas: patch.alphas(),
+            uvs: patch.uvs(),
+            domain: patch.domain(),
+        }
+    }
+}
+
+impl DynBezierRectangle<Vec3> {
+    //see BezierRectangle::normal
+    pub fn normal(&self, u: f32, v: f32) -> Vec3 {
+        let (_, du, dv, duv) = self.evaluate_with_mixed_derivative(u, v);
+        let n = math::surface_normal(u, v, du, dv, duv);
+        if n != Vec3::ZERO {
+            return n;
+        }
+        let (u, v) = (math::lerp(u, 0.5, 0.01), math::lerp(v, 0.5, 0.01));
+        let (_, du, dv, duv) = self.evaluate_with_mixed_derivative(u, v);
+        math::surface_normal(u, v, du, dv, duv)
+    }
+
+    //reads a patch of the given degrees in the same format as BezierRectangle::read_with_format
+    pub fn read(
+        tokens: &mut TokenStream,
+        degree_u: usize,
+        degree_v: usize,
+        format: NetFormat,
+        default_color: Color,
+    ) -> Result<Self, ParseError> {
+        let num_ctrl_pts = (degree_u + 1) * (degree_v + 1);
+        let (points, uvs, colors, alphas) = rectangle::read_net(tokens, num_ctrl_pts, format, default_color)?;
+        let points = points.into_iter().map(math::project).collect();
+        Ok(DynBezierRectangle::new(points, degree_u, degree_v, colors)
+            .with_alphas(alphas)
+            .with_uvs(uvs.unwrap_or(rectangle::CORNERS)))
+    }
+
+    //see FromString::from_string
+    pub fn from_string(lines: &str, degree_u: usize, degree_v: usize) -> Result<Self, ParseError> {
+        let mut tokens = TokenStream::new(lines);
+        let format = NetFormat {
+            colored: true,
+            ..NetFormat::default()
+        };
+        let patch = DynBezierRectangle::read(&mut tokens, degree_u, degree_v, format, Color::ONE)?;
+        match tokens.next() {
+            Some(t) => Err(ParseError::new(
+                t.line,
+                t.column,
+                format!("unexpected '{}' after the object", t.text),
+            )),
+            None => Ok(patch),
+        }
+    }
+}
+
+impl Transform for DynBezierRectangle<Vec3> {
+    fn transform(&mut self, m: Mat4) {
+        for p in &mut self.points {
+            *p = m.project_point3(*p);
+        }
+    }
+}
+
+impl Surface for DynBezierRectangle<Vec3> {
+    fn position(&self, u: f32, v: f32) -> Vec3 {
+        self.evaluate(u, v)
+    }
+
+    fn normal(&self, u: f32, v: f32) -> Vec3 {
+        self.normal(u, v)
+    }
+
+    fn color(&self, u: f32, v: f32) -> Color {
+        math::bilerp(self.colors[0], self.colors[2], self.colors[1], self.colors[3], u, v)
+    }
+
+    fn alpha(&self, u: f32, v: f32) -> f32 {
+        math::bilerp(self.alphas[0], self.alphas[2], self.alphas[1], self.alphas[3], u, v)
+    }
+
+    fn uv(&self, u: f32, v: f32) -> Vec2 {
+        math::bilerp(self.uvs[0], self.uvs[2], self.uvs[1], self.uvs[3], u, v)
+    }
+
+    fn boundary(&self, side: math::Side2D) -> Vec<Vec3> {
+        self.boundary(side)
+    }
+}
+
+impl DynBezierRectangle<Vec4> {
+    //the point on the rational patch at (u,v) with its derivatives, see math::project_derivatives
+    fn evaluate_projected(&self, u: f32, v: f32) -> (Vec3, Vec3, Vec3, Vec3) {
+        let (h, hu, hv, huv) = self.evaluate_with_mixed_derivative(u, v);
+        math::project_derivatives(h, hu, hv, huv)
+    }
+
+    //see BezierRectangle::normal
+    pub fn normal(&self, u: f32, v: f32) -> Vec3 {
+        let (_, du, dv, duv) = self.evaluate_projected(u, v);
+        let n = math::surface_normal(u, v, du, dv, duv);
+        if n != Vec3::ZERO {
+            return n;
+        }
+        let (u, v) = (math::lerp(u, 0.5, 0.01), math::lerp(v, 0.5, 0.01));
+        let (_, du, dv, duv) = self.evaluate_projected(u, v);
+        math::surface_normal(u, v, du, dv, duv)
+    }
+
+    //reads a rational patch of the given degrees, whose control points have four coordinates
+    pub fn read_rational(
+        tokens: &mut TokenStream,
+        degree_u: usize,
+        degree_v: usize,
+        format: NetFormat,
+        default_color: Color,
+    ) -> Result<Self, ParseError> {
+        let format = NetFormat {
+            rational: true,
+            ..format
+        };
+        let num_ctrl_pts = (degree_u + 1) * (degree_v + 1);
+        let (points, uvs, colors, alphas) = rectangle::read_net(tokens, num_ctrl_pts, format, default_color)?;
+        Ok(DynBezierRectangle::new(points, degree_u, degree_v, colors)
+            .with_alphas(alphas)
+            .with_uvs(uvs.unwrap_or(rectangle::CORNERS)))
+    }
+}
+
+//the homogeneous control points are transformed without projecting them, which is exact for any matrix
+impl Transform for DynBezierRectangle<Vec4> {
+    fn transform(&mut self, m: Mat4) {
+        for p in &mut self.points {
+            *p = m * *p;
+        }
+    }
+}
+
+impl Surface for DynBezierRectangle<Vec4> {
+    fn position(&self, u: f32, v: f32) -> Vec3 {
+        math::project(self.evaluate(u, v))
+    }
+
+    fn normal(&self, u: f32, v: f32) -> Vec3 {
+        self.normal(u, v)
+    }
+
+    fn color(&self, u: f32, v: f32) -> Color {
+        math::bilerp(self.colors[0], self.colors[2], self.colors[1], self.colors[3], u, v)
+    }
+
+    fn alpha(&self, u: f32, v: f32) -> f32 {
+        math::bilerp(self.alphas[0], self.alphas[2], self.alphas[1], self.alphas[3], u, v)
+    }
+
+    fn uv(&self, u: f32, v: f32) -> Vec2 {
+        math::bilerp(self.uvs[0], self.uvs[2], self.uvs[1], self.uvs[3], u, v)
+    }
+
+    //the projected control points, neighbours are found by these
+    fn boundary(&self, side: math::Side2D) -> Vec<Vec3> {
+        self.boundary(side).into_iter().map(math::project).collect()
+    }
+}
+
+//the flatness of the projected control net, which contains the patch as long as all weights are positive
+impl Flatness for DynBezierRectangle<Vec4> {
+    fn flatness(&self) -> f32 {
+        let projected: Vec<Vec3> = self.points.iter().copied().map(math::project).collect();
+        rectangle::net_flatness(&projected, self.degree_u, self.degree_v)
+    }
+}
+
+impl ToTriangle for DynBezierRectangle<Vec3> {
+    fn to_triangles(&self) -> Vec<Triangle<Vec3>> {
+        let (n, m) = (self.degree_u, self.degree_v);
+        let v00 = self.points[0];
+        let v01 = self.points[n];
+        let v10 = self.points[m * (n + 1)];
+        let v11 = self.points[m * (n + 1) + n];
+        let n0 = self.normal(0., 0.);
+        let n1 = self.normal(1., 0.);
+        let n2 = self.normal(0., 1.);
+        let n3 = self.normal(1., 1.);
+        let t1 = Triangle::new_with_normals(
+            [v00, v10, v01],
+            [self.colors[0], self.colors[1], self.colors[2]],
+            [n0, n2, n1],
+        )
+        .with_alphas([self.alphas[0], self.alphas[1], self.alphas[2]])
+        .with_uvs([self.uvs[0], self.uvs[1], self.uvs[2]]);
+        let t2 = Triangle::new_with_normals(
+            [v10, v11, v01],
+            [self.colors[1], self.colors[3], self.colors[2]],
+            [n2, n3, n1],
+        )
+        .with_alphas([self.alphas[1], self.alphas[3], self.alphas[2]])
+        .with_uvs([self.uvs[1], self.uvs[3], self.uvs[2]]);
+        vec![t1, t2]
+    }
+}
+
+impl Flatness for DynBezierRectangle<Vec3> {
+    fn flatness(&self) -> f32 {
+        rectangle::net_flatness(&self.points, self.degree_u, self.degree_v)
+    }
+}
+
+impl<T> Subdivide for DynBezierRectangle<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T> + std::fmt::Debug,
+{
+    fn subdivide(&self) -> Vec<Self> {
+        self.subdivide_cross()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_pair() -> (BezierRectangle<Vec3, 3, 2>, DynBezierRectangle<Vec3>) {
+        let mut pts = [Vec3::ZERO; 12];
+        for j in 0..3 {
+            for i in 0..4 {
+                pts[j * 4 + i] = Vec3::new(i as f32, j as f32, ((i * 7 + j * 3) % 5) as f32);
+            }
+        }
+        let colors = [
+            Color::new(1., 0., 0.),
+            Color::new(0., 1., 0.),
+            Color::new(0., 0., 1.),
+            Color::new(1., 1., 1.),
+        ];
+        let typed = BezierRectangle::<Vec3, 3, 2>::new(pts, colors);
+        (typed.clone(), DynBezierRectangle::from(typed))
+    }
+
+    #[test]
+    fn matches_typed_evaluation() {
+        let (typed, dynamic) = example_pair();
+        assert_eq!(dynamic.degree(), (3, 2));
+        for (u, v) in [(0., 0.), (0.3, 0.7), (1., 0.5), (0.5, 1.)] {
+            assert_eq!(
+                dynamic.evaluate_with_derivatives(u, v),
+                typed.evaluate_with_derivatives(u, v)
+            );
+            assert_eq!(dynamic.normal(u, v), typed.normal(u, v));
+        }
+        assert_eq!(dynamic.flatness(), typed.flatness());
+    }
+
+    #[test]
+    fn matches_typed_subdivision() {
+        let (typed, dynamic) = example_pair();
+        for (t, d) in typed.subdivide_cross().iter().zip(dynamic.subdivide_cross().iter()) {
+            assert_eq!(DynBezierRectangle::from(t.clone()).points, d.points);
+            assert_eq!(t.domain(), d.domain());
+            assert_eq!(t.to_triangles()[0].points(), d.to_triangles()[0].points());
+        }
+    }
+
+    #[test]
+    fn rational_cylinder() {
+        //a quarter of the unit cylinder around the z axis, u runs along the circle and v along the axis
+        let w = 0.5f32.sqrt();
+        let arc = [
+            Vec4::new(1., 0., 0., 1.),
+            Vec4::new(w, w, 0., w),
+            Vec4::new(0., 1., 0., 1.),
+        ];
+        let mut points = arc.to_vec();
+        points.extend(arc.map(|p| p + Vec4::new(0., 0., p.w, 0.)));
+        let patch = DynBezierRectangle::new(points, 2, 1, [Color::ONE; 4]);
+
+        let check = |patch: &DynBezierRectangle<Vec4>, u: f32, v: f32| {
+            let p = Surface::position(patch, u, v);
+            assert!(
+                (p.truncate().length() - 1.).abs() < 1e-6,
+                "{} is not on the cylinder",
+                p
+            );
+            let n = patch.normal(u, v);
+            assert!(
+                (n - p.truncate().extend(0.)).length() < 1e-5,
+                "{} is not the normal at {}",
+                n,
+                p
+            );
+        };
+        for (u, v) in [(0., 0.), (0.3, 0.2), (0.5, 0.5), (0.9, 1.), (1., 0.7)] {
+            check(&patch, u, v);
+        }
+        //subdivision in homogeneous coordinates keeps the pieces exact
+        for piece in patch.subdivide_cross() {
+            check(&piece, 0.5, 0.5);
+        }
+        assert_eq!(
+            Surface::boundary(&patch, math::Side2D::U1),
+            vec![Vec3::Y, Vec3::new(0., 1., 1.)]
+        );
+
+        let colored = NetFormat {
+            colored: true,
+            ..NetFormat::default()
+        };
+        let cbez214 = "1 0 0 1\n1 1 0 1\n0 2 0 2\n1 0 1 1\n1 1 1 1\n0 2 2 2\n1 1 1\n1 1 1\n1 1 1\n1 1 1";
+        let patch = DynBezierRectangle::read_rational(&mut TokenStream::new(cbez214), 2, 1, colored, Color::ONE);
+        assert_eq!(Surface::position(&patch.unwrap(), 1., 1.), Vec3::new(0., 1., 1.));
+        let broken = cbez214.replacen("0 2 0 2", "0 2 0 0", 1);
+        let e = DynBezierRectangle::read_rational(&mut TokenStream::new(&broken), 2, 1, colored, Color::ONE);
+        let e = e.unwrap_err();
+        assert_eq!((e.line, e.column), (3, 7));
+    }
+
+    #[test]
+    fn parse_non_square() {
+        let cbez513 = "0 0 0\n1 0 0\n2 0 0\n3 0 0\n4 0 0\n5 0 0\n0 1 0\n1 1 0\n2 1 1\n3 1 0\n4 1 0\n5 1 0\n\
+            1 0 0\n0 1 0\n0 0 1\n1 1 1";
+        let patch = DynBezierRectangle::from_string(cbez513, 5, 1).unwrap();
+        assert_eq!(patch.degree(), (5, 1));
+        assert_eq!(patch.evaluate(1., 1.), Vec3::new(5., 1., 0.));
+        assert_eq!(
+            patch.boundary(math::Side2D::U1),
+            vec![Vec3::new(5., 0., 0.), Vec3::new(5., 1., 0.)]
+        );
+        assert!(DynBezierRectangle::from_string(cbez513, 4, 1).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use bevy::prelude::{Mat4, Vec2, Vec3};
+use std::ops::{Add, Mul};
+use Vec3 as Color;
+
+use crate::bezier::rectangle::MAX_TYPED_DEGREE;
+use crate::bezier::triangle::{self, BezierTriangle};
+use crate::error::ParseError;
+use crate::math::{self, Transform};
+use crate::stitching::Surface;
+use crate::subdivision::{Flatness, Subdivide};
+use crate::tokens::TokenStream;
+use crate::triangle::{ToTriangle, Triangle};
+
+/*
+    A triangular bezier patch whose degree is only known at runtime
+    It behaves exactly like a BezierTriangle of the given degree, the control points, colors, alphas
+    and texture coordinates are laid out the same way, the builder uses it for the patches read from a file
+    The degree is at most MAX_TYPED_DEGREE, since the nets are evaluated in the same arrays as the typed ones
+*/
+#[derive(Debug, Clone)]
+pub struct DynBezierTriangle<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    points: Vec<T>,
+    degree: usize,
+    colors: [Color; 3],
+    alphas: [f32; 3],
+    uvs: [Vec2; 3],
+}
+
+impl<T> DynBezierTriangle<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    pub fn new(points: Vec<T>, degree: usize, colors: [Color; 3]) -> Self {
+        assert!(degree <= MAX_TYPED_DEGREE, "degree too large for a DynBezierTriangle");
+        assert_eq!(points.len(), math::triangular_number(degree + 1));
+        DynBezierTriangle {
+            points: points,
+            degree: degree,
+            colors: colors,
+            alphas: [1.; 3],
+            uvs: triangle::CORNERS,
+        }
+    }
+
+    pub fn with_alphas(mut self, alphas: [f32; 3]) -> Self {
+        self.alphas = alphas;
+        self
+    }
+
+    pub fn degree(&self) -> usize {
+        self.degree
+    }
+
+    pub fn points(&self) -> &[T] {
+        &self.points
+    }
+
+    pub fn colors(&self) -> [Color; 3] {
+        self.colors
+    }
+
+    pub fn evaluate(&self, u: f32, v: f32) -> T {
+        triangle::net_evaluate(&self.points, self.degree, u, v)
+    }
+
+    //see BezierTriangle::evaluate_with_derivatives
+    pub fn evaluate_with_derivatives(&self, u: f32, v: f32) -> (T, T, T) {
+        triangle::net_evaluate_with_derivatives(&self.points, self.degree, u, v)
+    }
+
+    //see BezierTriangle::sub_triangle
+    pub fn sub_triangle(&self, corners: [Vec2; 3]) -> Self {
+        DynBezierTriangle {
+            points: triangle::net_sub_triangle(&self.points, self.degree, corners),
+            degree: self.degree,
+            colors: corners.map(|c| triangle::interpolate(&self.colors, c)),
+            alphas: corners.map(|c| triangle::interpolate(&self.alphas, c)),
+            uvs: corners.map(|c| triangle::interpolate(&self.uvs, c)),
+        }
+    }
+
+    //see BezierTriangle::subdivide_midpoints
+    pub fn subdivide_midpoints(&self) -> Vec<Self> {
+        triangle::MIDPOINT_TRIANGLES
+            .iter()
+            .map(|corners| self.sub_triangle(*corners))
+            .collect()
+    }
+}
+
+impl<T, const N: usize> From<BezierTriangle<T, N>> for DynBezierTriangle<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    fn from(patch: BezierTriangle<T, N>) -> Self {
+        DynBezierTriangle {
+            points: patch.points().to_vec(),
+            degree: N,
+            colors: patch.colors(),
+            alphas: patch.alphas(),
+            uvs: patch.uvs(),
+        }
+    }
+}
+
+impl DynBezierTriangle<Vec3> {
+    //see BezierTriangle::normal
+    pub fn normal(&self, u: f32, v: f32) -> Vec3 {
+        triangle::net_normal(&self.points, self.degree, u, v)
+    }
+
+    //reads a patch of the given degree in the same format as BezierTriangle::read
+    pub fn read(
+        tokens: &mut TokenStream,
+        degree: usize,
+        colored: bool,
+        default_color: Color,
+    ) -> Result<Self, ParseError> {
+        let (points, colors, alphas) = triangle::read_net(tokens, degree, colored, default_color)?;
+        Ok(DynBezierTriangle::new(points, degree, colors).with_alphas(alphas))
+    }
+}
+
+//see the Surface implementation of BezierTriangle
+impl Surface for DynBezierTriangle<Vec3> {
+    fn position(&self, u: f32, v: f32) -> Vec3 {
+        let p = triangle::from_square(u, v);
+        self.evaluate(p.x, p.y)
+    }
+
+    fn normal(&self, u: f32, v: f32) -> Vec3 {
+        let p = triangle::from_square(u, v);
+        self.normal(p.x, p.y)
+    }
+
+    fn color(&self, u: f32, v: f32) -> Color {
+        triangle::interpolate(&self.colors, triangle::from_square(u, v))
+    }
+
+    fn alpha(&self, u: f32, v: f32) -> f32 {
+        triangle::interpolate(&self.alphas, triangle::from_square(u, v))
+    }
+
+    fn uv(&self, u: f32, v: f32) -> Vec2 {
+        triangle::interpolate(&self.uvs, triangle::from_square(u, v))
+    }
+
+    fn boundary(&self, side: math::Side2D) -> Vec<Vec3> {
+        triangle::net_boundary(&self.points, self.degree, side)
+    }
+}
+
+impl ToTriangle for DynBezierTriangle<Vec3> {
+    fn to_triangles(&self) -> Vec<Triangle<Vec3>> {
+        let points = [0, self.degree, self.points.len() - 1].map(|i| self.points[i]);
+        let normals = triangle::CORNERS.map(|c| self.normal(c.x, c.y));
+        let triangle = Triangle::new_with_normals(points, self.colors, normals);
+        vec![triangle.with_alphas(self.alphas).with_uvs(self.uvs)]
+    }
+}
+
+impl<T> Subdivide for DynBezierTriangle<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    fn subdivide(&self) -> Vec<Self> {
+        self.subdivide_midpoints()
+    }
+}
+
+impl Transform for DynBezierTriangle<Vec3> {
+    fn transform(&mut self, m: Mat4) {
+        for p in &mut self.points {
+            *p = m.project_point3(*p);
+        }
+    }
+}
+
+impl Flatness for DynBezierTriangle<Vec3> {
+    fn flatness(&self) -> f32 {
+        triangle::net_flatness(&self.points, self.degree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_typed_triangle() {
+        let pts = [
+            Vec3::new(0., 0., 0.),
+            Vec3::new(0.5, 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 0.5, 0.),
+            Vec3::new(0.5, 0.5, 0.5),
+            Vec3::new(0., 1., 0.),
+        ];
+        let typed = BezierTriangle::<_, 2>::new(pts, [Color::X, Color::Y, Color::Z]);
+        let patch = DynBezierTriangle::from(typed.clone());
+        assert_eq!(patch.degree(), 2);
+        for (u, v) in [(0., 0.), (0.2, 0.3), (0.5, 0.5)] {
+            assert_eq!(
+                patch.evaluate_with_derivatives(u, v),
+                typed.evaluate_with_derivatives(u, v)
+            );
+            assert_eq!(patch.normal(u, v), typed.normal(u, v));
+        }
+        for (piece, typed_piece) in patch.subdivide_midpoints().iter().zip(typed.subdivide_midpoints()) {
+            assert_eq!(piece.points(), typed_piece.points());
+            assert_eq!(piece.colors(), typed_piece.colors());
+        }
+        assert_eq!(patch.flatness(), typed.flatness());
+        for side in [math::Side2D::U0, math::Side2D::U1, math::Side2D::V0, math::Side2D::V1] {
+            assert_eq!(Surface::boundary(&patch, side), Surface::boundary(&typed, side));
+        }
+    }
+
+    #[test]
+    fn parse_bezier_triangle() {
+        //a flat linear patch with alpha at its first corner and a quadratic one without colors
+        let mut tokens = TokenStream::new("0 0 0\n2 0 0\n0 2 0\n1 0 0 0.5\n0 1 0\n0 0 1\n");
+        let patch = DynBezierTriangle::read(&mut tokens, 1, true, Color::ONE).unwrap();
+        assert_eq!(patch.degree(), 1);
+        assert_eq!(patch.evaluate(0.5, 0.25), Vec3::new(1., 0.5, 0.));
+        assert_eq!(patch.alphas, [0.5, 1., 1.]);
+        let quadratic = "0 0 0\n1 0 0\n2 0 0\n0 1 0\n1 1 1\n0 2 0\n";
+        let patch = DynBezierTriangle::read(&mut TokenStream::new(quadratic), 2, false, Color::ONE).unwrap();
+        assert_eq!(patch.colors(), [Color::ONE; 3]);
+        assert_eq!(
+            patch.to_triangles()[0].points(),
+            [Vec3::ZERO, Vec3::new(2., 0., 0.), Vec3::new(0., 2., 0.)]
+        );
+        let e = DynBezierTriangle::read(&mut TokenStream::new(quadratic), 3, false, Color::ONE).unwrap_err();
+        assert_eq!(e.reason, "missing coordinate, 30 values expected but 18 found");
+    }
+}
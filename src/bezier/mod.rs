@@ -1,3 +1,7 @@
+pub mod bspline;
 pub mod curve;
+pub mod dyn_curve;
+pub mod dyn_rectangle;
+pub mod dyn_triangle;
 pub mod rectangle;
 pub mod triangle;
@@ -1,284 +1,566 @@
-use bevy::prelude::Vec3;
+use bevy::prelude::{Mat4, Vec2, Vec3, Vec4};
 use std::ops::{Add, Mul};
 use Vec3 as Color;
 
-use crate::math;
-use crate::subdivision::Subdivide;
+use crate::error::ParseError;
+use crate::math::{self, Transform};
+use crate::stitching::Surface;
+use crate::subdivision::{Flatness, Subdivide};
+use crate::tokens::{self, TokenStream};
 use crate::triangle::{ToTriangle, Triangle};
 
 pub trait FromString {
-    fn from_string(lines: &str) -> Result<Self, String>
+    //reads the object from the values that follow in the token stream
+    fn read(tokens: &mut TokenStream) -> Result<Self, ParseError>
     where
         Self: Sized;
+
+    //reads the object from a string that contains nothing else
+    fn from_string(lines: &str) -> Result<Self, ParseError>
+    where
+        Self: Sized,
+    {
+        let mut tokens = TokenStream::new(lines);
+        let object = Self::read(&mut tokens)?;
+        match tokens.next() {
+            Some(t) => Err(ParseError::new(
+                t.line,
+                t.column,
+                format!("unexpected '{}' after the object", t.text),
+            )),
+            None => Ok(object),
+        }
+    }
 }
 /*
     A rectangular bezier patch of degree N in u direction and degree M in v direction
     control point b_ij is stored at points[j*(N+1) + i]
     the colors and parameters are matched as such:
         at (u,v) == (0,0) interpolates point b_00 and has color colors[0]
-        at (u,v) == (1,0) interpolates point b_N0 and has color colors[1]
-        at (u,v) == (0,1) interpolates point b_0M and has color colors[2]
+        at (u,v) == (1,0) interpolates point b_N0 and has color colors[2]
+        at (u,v) == (0,1) interpolates point b_0M and has color colors[1]
         at (u,v) == (1,1) interpolates point b_NM and has color colors[3]
+    alphas holds the opacity of the corner colors in the same order, 1 is opaque
+    uvs holds the texture coordinates of the corners in the same order, by default the (u,v) parameters of the corners
     In general it is easiest to imagine the following configuration:
 
         (0,0) -- u -- (1,0)               b_00 b_10 b_20 --- b_N0
-          |             |                 b_01                |                    c[0] - c[1]
+          |             |                 b_01                |                    c[0] - c[2]
           v             |      maps to    b_02                |      with colors    |      |
-          |             |                  |                  |                    c[2] - c[3]
+          |             |                  |                  |                    c[1] - c[3]
         (0,1) ------- (1,1)               b_0M b_1M b_2M --- b_NM
+
+    A patch created by subdivision keeps track of the part of the original patch's parameter domain it covers,
+    domain[0] is the (u,v) parameter of its corner (0,0) and domain[1] the one of its corner (1,1)
+
+    The degrees are part of the type, so the number of control points is checked at compile time.
+    Evaluation and subdivision work on rows and columns copied to arrays on the stack, whose loops have constant bounds,
+    so evaluating a typed patch does not allocate. The arrays hold MAX_TYPED_DEGREE+1 points,
+    which limits the degrees of typed patches, this is also the largest degree that can be read from a file.
+    DynBezierRectangle is the same patch for degrees only known at runtime, it uses the net_* functions below
 */
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BezierRectangle<T, const N: usize, const M: usize>
 where
     T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
-    [(); (N + 1) * (M + 1)]:,
 {
-    points: [T; (N + 1) * (M + 1)],
+    points: Vec<T>,
     colors: [Color; 4],
+    alphas: [f32; 4],
+    uvs: [Vec2; 4],
+    domain: [Vec2; 2],
+}
+
+//the largest degree in each direction of a BezierRectangle and of a BezierTriangle, see the comment above
+pub const MAX_TYPED_DEGREE: usize = 9;
+
+//the (u,v) parameters of the corners in the order of the colors
+pub const CORNERS: [Vec2; 4] = [Vec2::ZERO, Vec2::Y, Vec2::X, Vec2::ONE];
+
+//the optional values of a patch besides its control points, by default there are none
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetFormat {
+    //texture coordinates for the four corners follow the control points
+    pub textured: bool,
+    //the control points have a fourth homogeneous coordinate, their weight
+    pub rational: bool,
+    //the four corner colors follow the control points and texture coordinates, otherwise the default color is used
+    pub colored: bool,
 }
 
 impl<T, const N: usize, const M: usize> BezierRectangle<T, N, M>
 where
     T: Copy + Add<T, Output = T> + Mul<f32, Output = T> + std::fmt::Debug,
-    [(); (N + 1) * (M + 1)]:,
 {
-    pub fn new(points: [T; (N + 1) * (M + 1)], colors: [Color; 4]) -> Self {
+    //P is the number of control points, which has to be (N+1)*(M+1)
+    pub fn new<const P: usize>(points: [T; P], colors: [Color; 4]) -> Self {
+        const { assert!(P == (N + 1) * (M + 1), "wrong number of control points") };
+        const {
+            assert!(
+                N <= MAX_TYPED_DEGREE && M <= MAX_TYPED_DEGREE,
+                "degree too large for a BezierRectangle"
+            )
+        };
         BezierRectangle {
-            points: points,
+            points: points.to_vec(),
             colors: colors,
+            alphas: [1.; 4],
+            uvs: CORNERS,
+            domain: [Vec2::ZERO, Vec2::ONE],
         }
     }
 
-    fn evaluate(&self, u: f32, v: f32) -> T {
-        unimplemented!()
+    //the patch is opaque unless alphas are given
+    pub fn with_alphas(mut self, alphas: [f32; 4]) -> Self {
+        self.alphas = alphas;
+        self
     }
 
-    pub fn subdivide_cross(&self) -> Vec<Self>
-    where
-        [(); math::triangular_number(N + 1)]:,
-        [(); math::triangular_number(M + 1)]:,
-    {
-        let (l, r) = self.subdivide(math::Axis2D::U, 0.5);
-        let (tl, bl) = l.subdivide(math::Axis2D::V, 0.5);
-        let (tr, br) = r.subdivide(math::Axis2D::V, 0.5);
+    pub fn with_uvs(mut self, uvs: [Vec2; 4]) -> Self {
+        self.uvs = uvs;
+        self
+    }
 
-        vec![tl, bl, tr, br]
+    pub fn points(&self) -> &[T] {
+        &self.points
     }
 
-    pub fn subdivide(&self, axis: math::Axis2D, t: f32) -> (Self, Self)
-    where
-        [(); math::triangular_number(N + 1)]:,
-        [(); math::triangular_number(M + 1)]:,
-    {
-        match axis {
-            math::Axis2D::U => self.subdivide_u(t),
-            math::Axis2D::V => self.subdivide_v(t),
-        }
+    pub fn colors(&self) -> [Color; 4] {
+        self.colors
     }
 
-    fn subdivide_u(&self, t: f32) -> (Self, Self)
-    where
-        [(); math::triangular_number(N + 1)]:,
-    {
-        //control points for the new surfaces
-        let mut left = [self.points[0]; (N + 1) * (M + 1)];
-        let mut right = [self.points[0]; (N + 1) * (M + 1)];
+    pub fn alphas(&self) -> [f32; 4] {
+        self.alphas
+    }
 
-        //calculate the control points
+    pub fn uvs(&self) -> [Vec2; 4] {
+        self.uvs
+    }
+
+    pub fn domain(&self) -> [Vec2; 2] {
+        self.domain
+    }
+
+    //control points along the given side, ordered by increasing parameter
+    pub fn boundary(&self, side: math::Side2D) -> Vec<T> {
+        net_boundary(&self.points, N, M, side)
+    }
+
+    pub fn evaluate(&self, u: f32, v: f32) -> T {
+        self.evaluate_with_derivatives(u, v).0
+    }
+
+    /*
+        Evaluates the surface at (u,v) using the de casteljau algorithm
+        Returns the point on the surface as well as the partial derivatives in u and in v direction
+    */
+    pub fn evaluate_with_derivatives(&self, u: f32, v: f32) -> (T, T, T) {
+        let (point, du, dv, _) = self.evaluate_with_mixed_derivative(u, v);
+        (point, du, dv)
+    }
+
+    //like evaluate_with_derivatives, additionally returns the mixed second derivative in u and v, see net_evaluate
+    fn evaluate_with_mixed_derivative(&self, u: f32, v: f32) -> (T, T, T, T) {
+        let mut row = [self.points[0]; MAX_TYPED_DEGREE + 1];
+        let mut col = row;
+        let mut col_du = row;
         for j in 0..M + 1 {
-            //take each row seperately
-            let mut row = [self.points[0]; N + 1];
-            row[..].copy_from_slice(&self.points[j * (N + 1)..(j + 1) * (N + 1)]);
-            let row = row;
-
-            //compute new values
-            let res = math::compute_triangular_scheme::<T, { N + 1 }>(&row, t);
-
-            //put the new values in the right place
-            let mut row_offset = 0;
-            let mut row_len = N + 1;
-            for i in 0..N + 1 {
-                left[j * (N + 1) + i] = res[row_offset + 0]; //first element of the row put in left in forward order
-                right[(j + 1) * (N + 1) - (i + 1)] = res[row_offset + row_len - 1]; //last element of the row put in right in reverse order
-                row_offset += row_len;
-                row_len -= 1;
-            }
+            row[..N + 1].copy_from_slice(&self.points[j * (N + 1)..(j + 1) * (N + 1)]);
+            (col[j], col_du[j]) = math::point_and_derivative_in_place(&mut row[..N + 1], u);
         }
+        let (point, dv) = math::point_and_derivative_in_place(&mut col[..M + 1], v);
+        let (du, duv) = math::point_and_derivative_in_place(&mut col_du[..M + 1], v);
+        (point, du, dv, duv)
+    }
 
-        //calculate the new colors
-        let mut colors_left = [Color::new(0., 0., 0.); 4];
-        let mut colors_right = [Color::new(0., 0., 0.); 4];
-        let new_color_top = math::lerp(self.colors[0], self.colors[2], t);
-        let new_color_bot = math::lerp(self.colors[1], self.colors[3], t);
-        colors_left[0] = self.colors[0];
-        colors_left[1] = self.colors[1];
-        colors_left[2] = new_color_top;
-        colors_left[3] = new_color_bot;
-        colors_right[0] = new_color_top;
-        colors_right[1] = new_color_bot;
-        colors_right[2] = self.colors[2];
-        colors_right[3] = self.colors[3];
+    pub fn subdivide_cross(&self) -> Vec<Self> {
+        let (l, r) = self.subdivide(math::Axis2D::U, 0.5);
+        let (tl, bl) = l.subdivide(math::Axis2D::V, 0.5);
+        let (tr, br) = r.subdivide(math::Axis2D::V, 0.5);
+
+        vec![tl, bl, tr, br]
+    }
+
+    pub fn subdivide(&self, axis: math::Axis2D, t: f32) -> (Self, Self) {
+        //control points, colors and domains for the new surfaces
+        let (points_first, points_second) = self.subdivide_points(axis, t);
+        let (colors_first, colors_second) = split_colors(&self.colors, axis, t);
+        let (alphas_first, alphas_second) = split_colors(&self.alphas, axis, t);
+        let (uvs_first, uvs_second) = split_colors(&self.uvs, axis, t);
+        let (domain_first, domain_second) = split_domain(&self.domain, axis, t);
 
         //assemble everything into new patches
         (
-            BezierRectangle::<T, N, M>::new(left, colors_left),
-            BezierRectangle::<T, N, M>::new(right, colors_right),
+            BezierRectangle::<T, N, M> {
+                points: points_first,
+                colors: colors_first,
+                alphas: alphas_first,
+                uvs: uvs_first,
+                domain: domain_first,
+            },
+            BezierRectangle::<T, N, M> {
+                points: points_second,
+                colors: colors_second,
+                alphas: alphas_second,
+                uvs: uvs_second,
+                domain: domain_second,
+            },
         )
     }
 
-    fn subdivide_v(&self, t: f32) -> (Self, Self)
-    where
-        [(); math::triangular_number(M + 1)]:,
-    {
-        //control points for the new surfaces
-        let mut top = [self.points[0]; (N + 1) * (M + 1)];
-        let mut bot = [self.points[0]; (N + 1) * (M + 1)];
-
-        //calculate the control points
-        for i in 0..N + 1 {
-            //take each column seperately
-            let mut col = [self.points[0]; M + 1];
-            for j in 0..M + 1 {
-                col[j] = self.points[j * (N + 1) + i];
+    //same as net_subdivide, the columns are split on the stack
+    fn subdivide_points(&self, axis: math::Axis2D, t: f32) -> (Vec<T>, Vec<T>) {
+        let mut first = self.points.clone();
+        let mut second = self.points.clone();
+        match axis {
+            math::Axis2D::U => {
+                for j in 0..M + 1 {
+                    let row = j * (N + 1)..(j + 1) * (N + 1);
+                    math::triangular_scheme_edges(&mut second[row.clone()], &mut first[row], t);
+                }
             }
-            let col = col;
-
-            //compute new values
-            let res = math::compute_triangular_scheme::<T, { M + 1 }>(&col, t);
-
-            //put the new values in the right place
-            let mut row_offset = 0;
-            let mut row_len = M + 1;
-            for j in 0..M + 1 {
-                top[j * (N + 1) + i] = res[row_offset + 0]; //first element of the row put in left in forward order
-                bot[(M - j) * (N + 1) + i] = res[row_offset + row_len - 1]; //last element of the row put in right in reverse order
-                row_offset += row_len;
-                row_len -= 1;
+            math::Axis2D::V => {
+                let mut col = [self.points[0]; MAX_TYPED_DEGREE + 1];
+                let mut col_first = col;
+                for i in 0..N + 1 {
+                    for j in 0..M + 1 {
+                        col[j] = self.points[j * (N + 1) + i];
+                    }
+                    math::triangular_scheme_edges(&mut col[..M + 1], &mut col_first[..M + 1], t);
+                    for j in 0..M + 1 {
+                        first[j * (N + 1) + i] = col_first[j];
+                        second[j * (N + 1) + i] = col[j];
+                    }
+                }
             }
         }
+        (first, second)
+    }
+}
 
-        //calculate the new colors
-        let mut colors_top = [Color::new(0., 0., 0.); 4];
-        let mut colors_bot = [Color::new(0., 0., 0.); 4];
-        let new_color_left = math::lerp(self.colors[0], self.colors[1], t);
-        let new_color_right = math::lerp(self.colors[2], self.colors[3], t);
-        colors_top[0] = self.colors[0];
-        colors_top[1] = new_color_left;
-        colors_top[2] = self.colors[2];
-        colors_top[3] = new_color_right;
-        colors_bot[0] = new_color_left;
-        colors_bot[1] = self.colors[1];
-        colors_bot[2] = new_color_right;
-        colors_bot[3] = self.colors[3];
-
-        //assemble everything into new patches
-        (
-            BezierRectangle::<T, N, M>::new(top, colors_top),
-            BezierRectangle::<T, N, M>::new(bot, colors_bot),
-        )
+impl<const N: usize, const M: usize> BezierRectangle<Vec3, N, M> {
+    //the exact surface normal at (u,v), see math::surface_normal
+    pub fn normal(&self, u: f32, v: f32) -> Vec3 {
+        let (_, du, dv, duv) = self.evaluate_with_mixed_derivative(u, v);
+        let n = math::surface_normal(u, v, du, dv, duv);
+        if n != Vec3::ZERO {
+            return n;
+        }
+        //evaluate slightly towards the center if the derivatives do not give a normal
+        let (u, v) = (math::lerp(u, 0.5, 0.01), math::lerp(v, 0.5, 0.01));
+        let (_, du, dv, duv) = self.evaluate_with_mixed_derivative(u, v);
+        math::surface_normal(u, v, du, dv, duv)
     }
 }
 
-impl<const N: usize, const M: usize> BezierRectangle<Vec3, N, M>
-where
-    [(); (N + 1) * (M + 1)]:,
-{
-    #[allow(unused_parens)]
-    fn corner_normals(&self) -> (Vec3, Vec3, Vec3, Vec3) {
-        let b00u = (self.points[0 * (N + 1) + 1] - self.points[0 * (N + 1) + 0]);
-        let b00v = (self.points[1 * (N + 1) + 0] - self.points[0 * (N + 1) + 0]);
-        let b10u = -(self.points[0 * (N + 1) + N - 1] - self.points[0 * (N + 1) + N]);
-        let b10v = (self.points[1 * (N + 1) + N] - self.points[0 * (N + 1) + N]);
-        let b01u = (self.points[M * (N + 1) + 1] - self.points[M * (N + 1) + 0]);
-        let b01v = -(self.points[(M - 1) * (N + 1) + 0] - self.points[M * (N + 1) + 0]);
-        let b11u = -(self.points[M * (N + 1) + N - 1] - self.points[M * (N + 1) + N]);
-        let b11v = -(self.points[(M - 1) * (N + 1) + N] - self.points[M * (N + 1) + N]);
-        let n00 = b00u.cross(b00v).normalize_or_zero();
-        let n10 = b10u.cross(b10v).normalize_or_zero();
-        let n01 = b01u.cross(b01v).normalize_or_zero();
-        let n11 = b11u.cross(b11v).normalize_or_zero();
-        (n00, n10, n01, n11)
+impl<const N: usize, const M: usize> Surface for BezierRectangle<Vec3, N, M> {
+    fn position(&self, u: f32, v: f32) -> Vec3 {
+        self.evaluate(u, v)
+    }
+
+    fn normal(&self, u: f32, v: f32) -> Vec3 {
+        self.normal(u, v)
+    }
+
+    fn color(&self, u: f32, v: f32) -> Color {
+        math::bilerp(self.colors[0], self.colors[2], self.colors[1], self.colors[3], u, v)
+    }
+
+    fn alpha(&self, u: f32, v: f32) -> f32 {
+        math::bilerp(self.alphas[0], self.alphas[2], self.alphas[1], self.alphas[3], u, v)
+    }
+
+    fn uv(&self, u: f32, v: f32) -> Vec2 {
+        math::bilerp(self.uvs[0], self.uvs[2], self.uvs[1], self.uvs[3], u, v)
+    }
+
+    fn boundary(&self, side: math::Side2D) -> Vec<Vec3> {
+        self.boundary(side)
     }
 }
 
-impl<const N: usize, const M: usize> ToTriangle for BezierRectangle<Vec3, N, M>
-where
-    [(); (N + 1) * (M + 1)]:,
-{
+impl<const N: usize, const M: usize> ToTriangle for BezierRectangle<Vec3, N, M> {
     fn to_triangles(&self) -> Vec<Triangle<Vec3>> {
         let v00 = self.points[0];
         let v01 = self.points[N];
         let v10 = self.points[M * (N + 1)];
-        let v11 = self.points[M * (N + 1) + M];
-        let (n0, n1, n2, n3) = self.corner_normals();
+        let v11 = self.points[M * (N + 1) + N];
+        let n0 = self.normal(0., 0.);
+        let n1 = self.normal(1., 0.);
+        let n2 = self.normal(0., 1.);
+        let n3 = self.normal(1., 1.);
         let t1 = Triangle::new_with_normals(
             [v00, v10, v01],
             [self.colors[0], self.colors[1], self.colors[2]],
             [n0, n2, n1],
-        );
+        )
+        .with_alphas([self.alphas[0], self.alphas[1], self.alphas[2]])
+        .with_uvs([self.uvs[0], self.uvs[1], self.uvs[2]]);
         let t2 = Triangle::new_with_normals(
             [v10, v11, v01],
             [self.colors[1], self.colors[3], self.colors[2]],
             [n2, n3, n1],
-        );
+        )
+        .with_alphas([self.alphas[1], self.alphas[3], self.alphas[2]])
+        .with_uvs([self.uvs[1], self.uvs[3], self.uvs[2]]);
         vec![t1, t2]
     }
 }
 
-impl<const N: usize, const M: usize> FromString for BezierRectangle<Vec3, N, M>
+impl<const N: usize, const M: usize> BezierRectangle<Vec3, N, M> {
+    //reads a patch with the optional values given by format, see read_net
+    pub fn read_with_format(
+        tokens: &mut TokenStream,
+        format: NetFormat,
+        default_color: Color,
+    ) -> Result<Self, ParseError> {
+        const {
+            assert!(
+                N <= MAX_TYPED_DEGREE && M <= MAX_TYPED_DEGREE,
+                "degree too large for a BezierRectangle"
+            )
+        };
+        let (points, uvs, colors, alphas) = read_net(tokens, (N + 1) * (M + 1), format, default_color)?;
+        Ok(BezierRectangle::<Vec3, N, M> {
+            points: points.into_iter().map(math::project).collect(),
+            colors: colors,
+            alphas: alphas,
+            uvs: uvs.unwrap_or(CORNERS),
+            domain: [Vec2::ZERO, Vec2::ONE],
+        })
+    }
+}
+
+impl<const N: usize, const M: usize> FromString for BezierRectangle<Vec3, N, M> {
+    fn read(tokens: &mut TokenStream) -> Result<BezierRectangle<Vec3, N, M>, ParseError> {
+        let format = NetFormat {
+            colored: true,
+            ..NetFormat::default()
+        };
+        BezierRectangle::read_with_format(tokens, format, Color::ONE)
+    }
+}
+
+impl<T, const N: usize, const M: usize> Subdivide for BezierRectangle<T, N, M>
 where
-    [(); (N + 1) * (M + 1)]:,
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T> + std::fmt::Debug,
 {
-    fn from_string(lines: &str) -> Result<BezierRectangle<Vec3, N, M>, String> {
-        let num_ctrl_pts: usize = (N + 1) * (M + 1);
-        let mut points = [Vec3::new(0., 0., 0.); (N + 1) * (M + 1)];
-        let mut colors = [Color::new(0., 0., 0.); 4];
-        let mut index = 0;
-        for line in lines.lines() {
-            let mut value = Vec3::new(0., 0., 0.);
-            let mut line_iter = line.split_whitespace();
-            if let Some(x) = line_iter.next() {
-                value.x = x.parse::<f32>().unwrap();
-            } else {
-                return Err(format!("missing vector element"));
-            }
-            if let Some(y) = line_iter.next() {
-                value.y = y.parse::<f32>().unwrap();
-            } else {
-                return Err(format!("missing vector element"));
-            }
-            if let Some(z) = line_iter.next() {
-                value.z = z.parse::<f32>().unwrap();
-            } else {
-                return Err(format!("missing vector element"));
+    fn subdivide(&self) -> Vec<Self> {
+        self.subdivide_cross()
+    }
+}
+
+impl<const N: usize, const M: usize> Transform for BezierRectangle<Vec3, N, M> {
+    fn transform(&mut self, m: Mat4) {
+        for p in &mut self.points {
+            *p = m.project_point3(*p);
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> Flatness for BezierRectangle<Vec3, N, M> {
+    fn flatness(&self) -> f32 {
+        net_flatness(&self.points, N, M)
+    }
+}
+
+/*
+    The following functions work on a control net stored like the points of a BezierRectangle
+    of degree n in u direction and degree m in v direction, they are shared with DynBezierRectangle
+*/
+
+//control points along the given side, ordered by increasing parameter
+pub fn net_boundary<T: Copy>(points: &[T], n: usize, m: usize, side: math::Side2D) -> Vec<T> {
+    match side {
+        math::Side2D::U0 => (0..m + 1).map(|j| points[j * (n + 1)]).collect(),
+        math::Side2D::U1 => (0..m + 1).map(|j| points[j * (n + 1) + n]).collect(),
+        math::Side2D::V0 => points[..n + 1].to_vec(),
+        math::Side2D::V1 => points[m * (n + 1)..].to_vec(),
+    }
+}
+
+/*
+    Evaluates the net at (u,v) using the de casteljau algorithm
+    Each row is reduced to a point and its u-derivative at parameter u,
+    then the resulting column of points and the column of derivatives are reduced at parameter v
+    Returns the point, the derivatives in u and in v direction and the mixed second derivative
+*/
+pub fn net_evaluate<T>(points: &[T], n: usize, m: usize, u: f32, v: f32) -> (T, T, T, T)
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let mut rows = points.to_vec();
+    let mut col = Vec::<T>::with_capacity(m + 1);
+    let mut col_du = Vec::<T>::with_capacity(m + 1);
+    for row in rows.chunks_mut(n + 1) {
+        let (point, du) = math::point_and_derivative_in_place(row, u);
+        col.push(point);
+        col_du.push(du);
+    }
+
+    let (point, dv) = math::point_and_derivative_in_place(&mut col, v);
+    let (du, duv) = math::point_and_derivative_in_place(&mut col_du, v);
+    (point, du, dv, duv)
+}
+
+/*
+    Splits the net along axis at t by splitting every row (for U) or column (for V)
+    Returns the control points of the first patch, which contains the corner (0,0), and of the second patch
+*/
+pub fn net_subdivide<T>(points: &[T], n: usize, m: usize, axis: math::Axis2D, t: f32) -> (Vec<T>, Vec<T>)
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let mut first = points.to_vec();
+    let mut second = points.to_vec();
+    match axis {
+        math::Axis2D::U => {
+            //rows are contiguous and can be split right where they are
+            for j in 0..m + 1 {
+                let row = j * (n + 1)..(j + 1) * (n + 1);
+                math::triangular_scheme_edges(&mut second[row.clone()], &mut first[row], t);
             }
-            if index < num_ctrl_pts {
-                points[index] = value;
-                //println!("point {}", value);
-            } else if index < num_ctrl_pts + 4 {
-                colors[index - num_ctrl_pts] = value;
-                //println!("color {}", value);
+        }
+        math::Axis2D::V => {
+            let mut col = vec![points[0]; m + 1];
+            let mut col_first = vec![points[0]; m + 1];
+            for i in 0..n + 1 {
+                for j in 0..m + 1 {
+                    col[j] = points[j * (n + 1) + i];
+                }
+                math::triangular_scheme_edges(&mut col, &mut col_first, t);
+                for j in 0..m + 1 {
+                    first[j * (n + 1) + i] = col_first[j];
+                    second[j * (n + 1) + i] = col[j];
+                }
             }
-            index += 1;
         }
-        if index != (num_ctrl_pts + 4) {
-            let err_message = format!("{} coordinates given, {} expected", index, num_ctrl_pts);
-            return Err(err_message);
+    }
+    (first, second)
+}
+
+/*
+    The flatness of a patch is the largest distance of a control point to the bilinear patch spanned by the four corners
+    b_ij is compared to the point of the bilinear patch at (i/n, j/m)
+    Since the patch is drawn as two triangles, the twist of the bilinear patch is taken into account as well,
+    which is the distance between its center and the center of the diagonal the triangles share,
+    measured orthogonally to both diagonals
+*/
+pub fn net_flatness(points: &[Vec3], n: usize, m: usize) -> f32 {
+    let b00 = points[0];
+    let bn0 = points[n];
+    let b0m = points[m * (n + 1)];
+    let bnm = points[m * (n + 1) + n];
+    let param = |i: usize, degree: usize| if degree == 0 { 0. } else { i as f32 / degree as f32 };
+
+    let diagonal_normal = (bnm - b00).cross(b0m - bn0).normalize_or_zero();
+    let mut distance = ((b00 + bnm - bn0 - b0m) * 0.25).dot(diagonal_normal).abs();
+    for j in 0..m + 1 {
+        for i in 0..n + 1 {
+            let bilinear = math::bilerp(b00, bn0, b0m, bnm, param(i, n), param(j, m));
+            distance = distance.max(points[j * (n + 1) + i].distance(bilinear));
         }
-        Ok(BezierRectangle::<Vec3, N, M>::new(points, colors))
     }
+    distance
 }
 
-impl<T, const N: usize, const M: usize> Subdivide for BezierRectangle<T, N, M>
+//corner colors or alphas of the two patches that result from splitting a patch along axis at t
+pub fn split_colors<C>(colors: &[C; 4], axis: math::Axis2D, t: f32) -> ([C; 4], [C; 4])
 where
-    T: Copy + Add<T, Output = T> + Mul<f32, Output = T> + std::fmt::Debug,
-    [(); (N + 1) * (M + 1)]:,
-    [(); math::triangular_number(N + 1)]:,
-    [(); math::triangular_number(M + 1)]:,
+    C: Copy + Add<C, Output = C> + Mul<f32, Output = C>,
 {
-    fn subdivide(&self) -> Vec<Self> {
-        self.subdivide_cross()
+    match axis {
+        math::Axis2D::U => {
+            let new_color_top = math::lerp(colors[0], colors[2], t);
+            let new_color_bot = math::lerp(colors[1], colors[3], t);
+            (
+                [colors[0], colors[1], new_color_top, new_color_bot],
+                [new_color_top, new_color_bot, colors[2], colors[3]],
+            )
+        }
+        math::Axis2D::V => {
+            let new_color_left = math::lerp(colors[0], colors[1], t);
+            let new_color_right = math::lerp(colors[2], colors[3], t);
+            (
+                [colors[0], new_color_left, colors[2], new_color_right],
+                [new_color_left, colors[1], new_color_right, colors[3]],
+            )
+        }
+    }
+}
+
+//domains of the two patches that result from splitting a patch along axis at t
+pub fn split_domain(domain: &[Vec2; 2], axis: math::Axis2D, t: f32) -> ([Vec2; 2], [Vec2; 2]) {
+    let [start, end] = *domain;
+    match axis {
+        math::Axis2D::U => {
+            let split = math::lerp(start.x, end.x, t);
+            ([start, Vec2::new(split, end.y)], [Vec2::new(split, start.y), end])
+        }
+        math::Axis2D::V => {
+            let split = math::lerp(start.y, end.y, t);
+            ([start, Vec2::new(end.x, split)], [Vec2::new(start.x, split), end])
+        }
+    }
+}
+
+//the control points in homogeneous coordinates, texture coordinates, colors and alphas of a patch
+type Net = (Vec<Vec4>, Option<[Vec2; 4]>, [Color; 4], [f32; 4]);
+
+/*
+    reads num_ctrl_pts control points, then the texture coordinates of the four corners if the format has them,
+    followed by the four corner colors and their alphas if the format has them, otherwise the patch gets the default color
+    control points of rational patches have four coordinates, the others three and get a weight of 1
+    colors are given as rgba, the alpha value is optional if it would be on the next line, see tokens::parse_colors
+    all values of the patch are taken from the stream before they are parsed,
+    so that an invalid value does not affect the objects after it
+*/
+pub fn read_net(
+    tokens: &mut TokenStream,
+    num_ctrl_pts: usize,
+    format: NetFormat,
+    default_color: Color,
+) -> Result<Net, ParseError> {
+    let dimension = if format.rational { 4 } else { 3 };
+    let points = tokens.take(dimension * num_ctrl_pts, "coordinate")?;
+    let uv_values = match format.textured {
+        true => tokens.take(8, "texture coordinate")?,
+        false => Vec::new(),
+    };
+    let mut color_values = Vec::new();
+    for _ in 0..if format.colored { 4 } else { 0 } {
+        let mut values = tokens.take(3, "color component")?;
+        values.extend(tokens.take_rest_of_line(1));
+        color_values.push(values);
+    }
+    let mut homogeneous = Vec::with_capacity(num_ctrl_pts);
+    for point in points.chunks_exact(dimension) {
+        let c = tokens::parse_all::<f32>(point, "coordinate")?;
+        let w = c.get(3).copied().unwrap_or(1.);
+        if w == 0. {
+            let t = &point[3];
+            return Err(ParseError::new(
+                t.line,
+                t.column,
+                format!("invalid weight '{}', it must not be 0", t.text),
+            ));
+        }
+        homogeneous.push(Vec4::new(c[0], c[1], c[2], w));
+    }
+    let uvs = match format.textured {
+        true => {
+            let values = tokens::parse_all::<f32>(&uv_values, "texture coordinate")?;
+            Some([0, 1, 2, 3].map(|i| Vec2::new(values[2 * i], values[2 * i + 1])))
+        }
+        false => None,
+    };
+    let mut colors = [default_color; 4];
+    let mut alphas = [1.; 4];
+    for (i, color) in tokens::parse_colors(&color_values)?.into_iter().enumerate() {
+        colors[i] = color.truncate();
+        alphas[i] = color.w;
     }
+    Ok((homogeneous, uvs, colors, alphas))
 }
 
 #[cfg(test)]
@@ -371,15 +653,61 @@ mod tests {
         }
     }
 
-    /*
-    These tests fail as they are no longer coherent with the implementation which has been matched to fit known inputs
-    most likely the problem is just a mix-up of axes
+    #[test]
+    fn parse_alpha() {
+        //the alpha value is optional for every corner
+        let cbez113 = "0 0 0\n1 0 0\n0 1 0\n1 1 0\n1 0 0 0.5\n0 1 0\n0 0 1 0\n1 1 1 1";
+        let patch = BezierRectangle::<Vec3, 1, 1>::from_string(cbez113).unwrap();
+        assert_eq!(patch.colors()[0], Color::new(1., 0., 0.));
+        assert_eq!(patch.alphas(), [0.5, 1., 0., 1.]);
+
+        //alphas are interpolated like the colors
+        let (l, r) = patch.subdivide(math::Axis2D::U, 0.5);
+        assert_eq!(l.alphas(), [0.5, 1., 0.25, 1.]);
+        assert_eq!(r.alphas(), [0.25, 1., 0., 1.]);
+        assert_eq!(patch.to_triangles()[0].alphas(), [0.5, 1., 0.]);
+        assert_eq!(Surface::alpha(&patch, 0.5, 0.5), 0.625);
+    }
+
+    #[test]
+    fn texture_coordinates() {
+        let points = "0 0 0\n1 0 0\n0 1 0\n1 1 0\n";
+        let colors = "1 0 0\n0 1 0\n0 0 1\n1 1 1";
+        let mut tokens = TokenStream::new(&format!("{}0 0 0 1 1 0 1 1\n{}", points, colors));
+        let format = NetFormat {
+            textured: true,
+            rational: false,
+            colored: true,
+        };
+        let patch = BezierRectangle::<Vec3, 1, 1>::read_with_format(&mut tokens, format, Color::ONE).unwrap();
+        assert_eq!(patch.uvs(), [Vec2::ZERO, Vec2::Y, Vec2::X, Vec2::ONE]);
+        assert_eq!(patch.colors()[2], Color::new(0., 0., 1.));
+
+        //without texture coordinates the parameters of the corners are used, also after subdivision
+        let patch = BezierRectangle::<Vec3, 1, 1>::from_string(&format!("{}{}", points, colors)).unwrap();
+        assert_eq!(patch.uvs(), CORNERS);
+        let (l, _) = patch.subdivide(math::Axis2D::U, 0.5);
+        let (_, bl) = l.subdivide(math::Axis2D::V, 0.5);
+        assert_eq!(
+            bl.uvs(),
+            [
+                Vec2::new(0., 0.5),
+                Vec2::new(0., 1.),
+                Vec2::new(0.5, 0.5),
+                Vec2::new(0.5, 1.)
+            ]
+        );
+        assert_eq!(bl.to_triangles()[1].uvs()[1], Vec2::new(0.5, 1.));
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
     #[test]
     fn bezier_rectangle_subdivide_u() {
         let surf = example_bezier_rectangle();
         let (l, r) = surf.subdivide(math::Axis2D::U, 0.5);
-        println!("left {:?}", l);
-        println!("right {:?}", r);
 
         //points at the split line match
         assert_eq!(l.points[0 + 3], r.points[0 + 0]);
@@ -395,20 +723,22 @@ mod tests {
         assert_eq!(r.points[8 + 3], surf.points[8 + 3]);
 
         //test colors
-        assert_eq!(l.colors[1], Color::new(0.5, 0.5, 0.));
-        assert_eq!(l.colors[3], Color::new(0.5, 0.5, 1.));
-        assert_eq!(r.colors[0], Color::new(0.5, 0.5, 0.));
-        assert_eq!(r.colors[2], Color::new(0.5, 0.5, 1.));
-
-        //TODO: make a test for evaluating l(.5, .5) == surf(.25, .5) once evaluation is implemented
+        assert_eq!(l.colors[2], Color::new(0.5, 0.0, 0.5));
+        assert_eq!(l.colors[3], Color::new(0.5, 1.0, 0.5));
+        assert_eq!(r.colors[0], Color::new(0.5, 0.0, 0.5));
+        assert_eq!(r.colors[1], Color::new(0.5, 1.0, 0.5));
+
+        //both halves agree with the original surface
+        for (u, v) in [(0., 0.), (0.5, 0.5), (0.25, 0.75), (1., 0.3)] {
+            assert_close(l.evaluate(u, v), surf.evaluate(u * 0.5, v));
+            assert_close(r.evaluate(u, v), surf.evaluate(0.5 + u * 0.5, v));
+        }
     }
 
     #[test]
     fn bezier_rectangle_subdivide_v() {
         let surf = example_bezier_rectangle();
         let (t, b) = surf.subdivide(math::Axis2D::V, 0.5);
-        println!("top {:?}", t);
-        println!("bottom {:?}", b);
 
         //points at the split line match
         assert_eq!(t.points[8 + 0], b.points[0 + 0]);
@@ -427,14 +757,121 @@ mod tests {
         assert_eq!(b.points[8 + 3], surf.points[8 + 3]);
 
         //test colors
-        assert_eq!(t.colors[2], Color::new(0.5, 0.0, 0.5));
-        assert_eq!(t.colors[3], Color::new(0.5, 1.0, 0.5));
-        assert_eq!(b.colors[0], Color::new(0.5, 0.0, 0.5));
-        assert_eq!(b.colors[1], Color::new(0.5, 1.0, 0.5));
+        assert_eq!(t.colors[1], Color::new(0.5, 0.5, 0.));
+        assert_eq!(t.colors[3], Color::new(0.5, 0.5, 1.));
+        assert_eq!(b.colors[0], Color::new(0.5, 0.5, 0.));
+        assert_eq!(b.colors[2], Color::new(0.5, 0.5, 1.));
+
+        //both halves agree with the original surface
+        for (u, v) in [(0., 0.), (0.5, 0.5), (0.75, 0.25), (0.3, 1.)] {
+            assert_close(t.evaluate(u, v), surf.evaluate(u, v * 0.5));
+            assert_close(b.evaluate(u, v), surf.evaluate(u, 0.5 + v * 0.5));
+        }
+    }
 
-        //TODO: make a test for evaluating t(.5, .5) == surf(.5, .25) once evaluation is implemented
+    #[test]
+    fn bezier_rectangle_evaluate() {
+        let surf = example_bezier_rectangle();
+
+        //corners are interpolated
+        assert_eq!(surf.evaluate(0., 0.), surf.points[0]);
+        assert_eq!(surf.evaluate(1., 0.), surf.points[3]);
+        assert_eq!(surf.evaluate(0., 1.), surf.points[8]);
+        assert_eq!(surf.evaluate(1., 1.), surf.points[11]);
+
+        //partial derivatives match finite differences
+        let h = 1e-3;
+        let (u, v) = (0.3, 0.6);
+        let (_, du, dv) = surf.evaluate_with_derivatives(u, v);
+        let du_approx = (surf.evaluate(u + h, v) - surf.evaluate(u - h, v)) / (2. * h);
+        let dv_approx = (surf.evaluate(u, v + h) - surf.evaluate(u, v - h)) / (2. * h);
+        assert!((du - du_approx).abs() < 1e-2);
+        assert!((dv - dv_approx).abs() < 1e-2);
+    }
+
+    #[test]
+    fn matches_net_functions() {
+        //the typed patch has its own evaluation and subdivision, which agree with the ones for runtime degrees
+        let surf = example_bezier_rectangle();
+        for (u, v) in [(0., 0.), (0.3, 0.6), (1., 0.25)] {
+            assert_eq!(
+                surf.evaluate_with_mixed_derivative(u, v),
+                net_evaluate(&surf.points, 3, 2, u, v)
+            );
+        }
+        for axis in [math::Axis2D::U, math::Axis2D::V] {
+            let (first, second) = surf.subdivide(axis, 0.3);
+            assert_eq!(
+                (first.points, second.points),
+                net_subdivide(&surf.points, 3, 2, axis, 0.3)
+            );
+        }
+    }
+
+    #[test]
+    fn bezier_rectangle_normal() {
+        //a bilinear patch in the xy-plane has the z-axis as normal everywhere
+        let pts = [
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(2., 2., 0.),
+        ];
+        let surf = BezierRectangle::<Vec3, 1, 1>::new(pts, [Color::new(0., 0., 0.); 4]);
+        assert_eq!(surf.normal(0., 0.), Vec3::Z);
+        assert_eq!(surf.normal(0.5, 0.5), Vec3::Z);
+        assert_eq!(surf.normal(1., 1.), Vec3::Z);
+    }
+
+    #[test]
+    fn bezier_rectangle_collapsed_edge_normal() {
+        //the v == 0 edge is collapsed into the origin, the patch is a flat triangle in the xy-plane
+        let pts = [
+            Vec3::new(0., 0., 0.),
+            Vec3::new(0., 0., 0.),
+            Vec3::new(-1., 1., 0.),
+            Vec3::new(1., 1., 0.),
+        ];
+        let surf = BezierRectangle::<Vec3, 1, 1>::new(pts, [Color::new(0., 0., 0.); 4]);
+        assert_eq!(surf.normal(0., 0.), Vec3::Z);
+        assert_eq!(surf.normal(0.5, 0.), Vec3::Z);
+        assert_eq!(surf.normal(1., 0.), Vec3::Z);
+
+        //subdivided patches touching the collapsed edge keep the normal
+        for part in surf.subdivide_cross() {
+            for t in part.to_triangles() {
+                assert_eq!(t.normals(), [Vec3::Z; 3]);
+            }
+        }
+    }
+
+    #[test]
+    fn bezier_rectangle_flatness() {
+        //a planar bilinear patch is flat
+        let pts = [
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(2., 2., 0.),
+        ];
+        let surf = BezierRectangle::<Vec3, 1, 1>::new(pts, [Color::new(0., 0., 0.); 4]);
+        assert_eq!(surf.flatness(), 0.);
+
+        //a twisted one is not
+        let pts = [
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(1., 1., 4.),
+        ];
+        let surf = BezierRectangle::<Vec3, 1, 1>::new(pts, [Color::new(0., 0., 0.); 4]);
+        assert!(surf.flatness() > 0.);
+
+        //subdivision makes patches flatter
+        let (l, r) = surf.subdivide(math::Axis2D::U, 0.5);
+        assert!(l.flatness() < surf.flatness());
+        assert!(r.flatness() < surf.flatness());
     }
-    */
 
     #[test]
     fn bezier_rectangle_subdivide() {
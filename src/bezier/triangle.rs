@@ -1,47 +1,508 @@
-use bevy::prelude::Vec3;
+use bevy::prelude::{Mat4, Vec2, Vec3};
 use std::ops::{Add, Mul};
 use Vec3 as Color;
 
-use crate::math;
-use crate::triangle::Triangle;
+use crate::bezier::rectangle::MAX_TYPED_DEGREE;
+use crate::error::ParseError;
+use crate::math::{self, Transform};
+use crate::stitching::Surface;
+use crate::subdivision::{Flatness, Subdivide};
+use crate::tokens::{self, TokenStream};
+use crate::triangle::{ToTriangle, Triangle};
 
+/*
+    A triangular bezier patch of degree N
+    It is defined over the parameter triangle u >= 0, v >= 0, u + v <= 1 with w = 1 - u - v,
+    control point b_ijk with i + j + k == N has the barycentric coordinates (i, j, k) / N in (u, v, w)
+    The points are stored row by row with increasing j, each row with increasing i:
+        b_00N b_10(N-1) ... b_N00          (u,v) == (0,0) -- (1,0)
+        b_01(N-1) ... b_(N-1)10
+        ...                                   colors[0] at (0,0), colors[1] at (1,0) and colors[2] at (0,1)
+        b_0N0                               (0,1)
+    b_ij is stored at points[net_index(N, i, j)]
+    alphas and uvs belong to the corners in the same order as the colors, uvs are the corner parameters by default
+
+    Like a BezierRectangle the patch is evaluated on a copy of its net in an array on the stack,
+    which holds the net of degree MAX_TYPED_DEGREE, so evaluation and subdivision do not allocate besides the new nets
+    DynBezierTriangle is the same patch for degrees only known at runtime, both use the net_* functions below
+*/
+#[derive(Debug, Clone)]
 pub struct BezierTriangle<T, const N: usize>
 where
     T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
-    [(); math::triangular_number(N + 1)]:,
 {
-    points: [T; math::triangular_number(N + 1)],
+    points: Vec<T>,
     colors: [Color; 3],
+    alphas: [f32; 3],
+    uvs: [Vec2; 3],
 }
 
+//the (u,v) parameters of the corners in the order of the colors
+pub const CORNERS: [Vec2; 3] = [Vec2::ZERO, Vec2::X, Vec2::Y];
+
+//the number of control points of a net of degree MAX_TYPED_DEGREE, the size of the arrays the nets are evaluated in
+const MAX_NET_SIZE: usize = math::triangular_number(MAX_TYPED_DEGREE + 1);
+
 impl<T, const N: usize> BezierTriangle<T, N>
 where
     T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
-    [(); math::triangular_number(N + 1)]:,
 {
-    pub fn new(points: [T; math::triangular_number(N + 1)], colors: [Color; 3]) -> Self {
+    //P is the number of control points, which has to be the triangular number of N+1
+    pub fn new<const P: usize>(points: [T; P], colors: [Color; 3]) -> Self {
+        const { assert!(P == math::triangular_number(N + 1), "wrong number of control points") };
+        const { assert!(N <= MAX_TYPED_DEGREE, "degree too large for a BezierTriangle") };
+        BezierTriangle {
+            points: points.to_vec(),
+            colors: colors,
+            alphas: [1.; 3],
+            uvs: CORNERS,
+        }
+    }
+
+    pub fn with_alphas(mut self, alphas: [f32; 3]) -> Self {
+        self.alphas = alphas;
+        self
+    }
+
+    pub fn points(&self) -> &[T] {
+        &self.points
+    }
+
+    pub fn colors(&self) -> [Color; 3] {
+        self.colors
+    }
+
+    pub fn alphas(&self) -> [f32; 3] {
+        self.alphas
+    }
+
+    pub fn uvs(&self) -> [Vec2; 3] {
+        self.uvs
+    }
+
+    pub fn evaluate(&self, u: f32, v: f32) -> T {
+        net_evaluate(&self.points, N, u, v)
+    }
+
+    //see net_evaluate_with_derivatives
+    pub fn evaluate_with_derivatives(&self, u: f32, v: f32) -> (T, T, T) {
+        net_evaluate_with_derivatives(&self.points, N, u, v)
+    }
+
+    /*
+        The patch over the part of the parameter triangle with the given corners,
+        which become the corners (0,0), (1,0) and (0,1) of the new patch, see net_sub_triangle
+    */
+    pub fn sub_triangle(&self, corners: [Vec2; 3]) -> Self {
         BezierTriangle {
+            points: net_sub_triangle(&self.points, N, corners),
+            colors: corners.map(|c| interpolate(&self.colors, c)),
+            alphas: corners.map(|c| interpolate(&self.alphas, c)),
+            uvs: corners.map(|c| interpolate(&self.uvs, c)),
+        }
+    }
+
+    //splits the patch at the midpoints of its edges into four patches, the last one is the middle one
+    pub fn subdivide_midpoints(&self) -> Vec<Self> {
+        MIDPOINT_TRIANGLES
+            .iter()
+            .map(|corners| self.sub_triangle(*corners))
+            .collect()
+    }
+}
+
+impl<const N: usize> BezierTriangle<Vec3, N> {
+    //see net_normal
+    pub fn normal(&self, u: f32, v: f32) -> Vec3 {
+        net_normal(&self.points, N, u, v)
+    }
+
+    //reads a patch in the format of read_net
+    pub fn read(tokens: &mut TokenStream, colored: bool, default_color: Color) -> Result<Self, ParseError> {
+        const { assert!(N <= MAX_TYPED_DEGREE, "degree too large for a BezierTriangle") };
+        let (points, colors, alphas) = read_net(tokens, N, colored, default_color)?;
+        Ok(BezierTriangle {
             points: points,
             colors: colors,
+            alphas: alphas,
+            uvs: CORNERS,
+        })
+    }
+}
+
+/*
+    A triangular patch is stitched like a rectangular patch whose side v == 1 is collapsed into the corner (0,1),
+    the point (u,v) of the unit square is the point (u(1-v), v) of the parameter triangle
+    the sides u == 0 and v == 0 are the edges along the parameter axes and the side u == 1 is the third edge,
+    from the corner (1,0) to the corner (0,1)
+*/
+pub fn from_square(u: f32, v: f32) -> Vec2 {
+    Vec2::new(u * (1. - v), v)
+}
+
+impl<const N: usize> Surface for BezierTriangle<Vec3, N> {
+    fn position(&self, u: f32, v: f32) -> Vec3 {
+        let p = from_square(u, v);
+        self.evaluate(p.x, p.y)
+    }
+
+    fn normal(&self, u: f32, v: f32) -> Vec3 {
+        let p = from_square(u, v);
+        self.normal(p.x, p.y)
+    }
+
+    fn color(&self, u: f32, v: f32) -> Color {
+        interpolate(&self.colors, from_square(u, v))
+    }
+
+    fn alpha(&self, u: f32, v: f32) -> f32 {
+        interpolate(&self.alphas, from_square(u, v))
+    }
+
+    fn uv(&self, u: f32, v: f32) -> Vec2 {
+        interpolate(&self.uvs, from_square(u, v))
+    }
+
+    fn boundary(&self, side: math::Side2D) -> Vec<Vec3> {
+        net_boundary(&self.points, N, side)
+    }
+}
+
+impl<const N: usize> ToTriangle for BezierTriangle<Vec3, N> {
+    fn to_triangles(&self) -> Vec<Triangle<Vec3>> {
+        let points = [0, N, self.points.len() - 1].map(|i| self.points[i]);
+        let normals = CORNERS.map(|c| self.normal(c.x, c.y));
+        let triangle = Triangle::new_with_normals(points, self.colors, normals);
+        vec![triangle.with_alphas(self.alphas).with_uvs(self.uvs)]
+    }
+}
+
+impl<T, const N: usize> Subdivide for BezierTriangle<T, N>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    fn subdivide(&self) -> Vec<Self> {
+        self.subdivide_midpoints()
+    }
+}
+
+impl<const N: usize> Transform for BezierTriangle<Vec3, N> {
+    fn transform(&mut self, m: Mat4) {
+        for p in &mut self.points {
+            *p = m.project_point3(*p);
+        }
+    }
+}
+
+impl<const N: usize> Flatness for BezierTriangle<Vec3, N> {
+    fn flatness(&self) -> f32 {
+        net_flatness(&self.points, N)
+    }
+}
+
+//the corners of the four parts subdivide_midpoints splits the parameter triangle into, the middle one is the last one
+pub const MIDPOINT_TRIANGLES: [[Vec2; 3]; 4] = [
+    [Vec2::ZERO, Vec2::new(0.5, 0.), Vec2::new(0., 0.5)],
+    [Vec2::new(0.5, 0.), Vec2::X, Vec2::new(0.5, 0.5)],
+    [Vec2::new(0., 0.5), Vec2::new(0.5, 0.5), Vec2::Y],
+    [Vec2::new(0.5, 0.5), Vec2::new(0., 0.5), Vec2::new(0.5, 0.)],
+];
+
+//index of control point b_ij in a triangular net of the given degree, see BezierTriangle
+pub fn net_index(degree: usize, i: usize, j: usize) -> usize {
+    //rows 0..j hold (degree + 1) + degree + ... + (degree + 2 - j) points
+    j * (degree + 2) - math::triangular_number(j) + i
+}
+
+//linear interpolation of values given at the corners
+pub fn interpolate<C>(values: &[C; 3], p: Vec2) -> C
+where
+    C: Copy + Add<C, Output = C> + Mul<f32, Output = C>,
+{
+    values[0] * (1. - p.x - p.y) + values[1] * p.x + values[2] * p.y
+}
+
+//the point at (u,v) of the patch with the given net
+pub fn net_evaluate<T>(points: &[T], degree: usize, u: f32, v: f32) -> T
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let mut net = [points[0]; MAX_NET_SIZE];
+    net[..points.len()].copy_from_slice(points);
+    blossom_in_place(&mut net, degree, &[Vec2::new(u, v); MAX_TYPED_DEGREE][..degree])
+}
+
+/*
+    Evaluates the patch at (u,v) using the de casteljau algorithm
+    Returns the point as well as the partial derivatives in u and in v direction
+    The last step of the algorithm interpolates the three points b_u, b_v and b_w,
+    the derivatives are degree * (b_u - b_w) and degree * (b_v - b_w)
+*/
+pub fn net_evaluate_with_derivatives<T>(points: &[T], degree: usize, u: f32, v: f32) -> (T, T, T)
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    if degree == 0 {
+        return (points[0], points[0] * 0., points[0] * 0.);
+    }
+    let mut net = [points[0]; MAX_NET_SIZE];
+    net[..points.len()].copy_from_slice(points);
+    for d in (2..degree + 1).rev() {
+        de_casteljau_step(&mut net, d, Vec2::new(u, v));
+    }
+    let (bw, bu, bv) = (net[0], net[1], net[2]);
+    let point = bu * u + bv * v + bw * (1. - u - v);
+    let degree = degree as f32;
+    (point, (bu + bw * -1.) * degree, (bv + bw * -1.) * degree)
+}
+
+/*
+    The net of the patch over the part of the parameter triangle with the given corners
+    Every control point of the new net is a blossom of the old one:
+    b_ijk is the blossom with i arguments corners[1], j arguments corners[2] and k arguments corners[0]
+*/
+pub fn net_sub_triangle<T>(points: &[T], degree: usize, corners: [Vec2; 3]) -> Vec<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let mut sub_points = Vec::with_capacity(points.len());
+    let mut net = [points[0]; MAX_NET_SIZE];
+    let mut args = [corners[0]; MAX_TYPED_DEGREE];
+    for j in 0..degree + 1 {
+        for i in 0..degree + 1 - j {
+            args[..i].fill(corners[1]);
+            args[i..i + j].fill(corners[2]);
+            args[i + j..degree].fill(corners[0]);
+            net[..points.len()].copy_from_slice(points);
+            sub_points.push(blossom_in_place(&mut net, degree, &args[..degree]));
         }
     }
-    fn evaluate(&self, u: f32, v: f32) -> T {
-        unimplemented!()
+    sub_points
+}
+
+//the surface normal at (u,v), evaluated slightly towards the center if the derivatives do not give one
+pub fn net_normal(points: &[Vec3], degree: usize, u: f32, v: f32) -> Vec3 {
+    let (_, du, dv) = net_evaluate_with_derivatives(points, degree, u, v);
+    let n = du.cross(dv).normalize_or_zero();
+    if n != Vec3::ZERO {
+        return n;
+    }
+    let p = Vec2::new(u, v).lerp(Vec2::splat(1. / 3.), 0.01);
+    let (_, du, dv) = net_evaluate_with_derivatives(points, degree, p.x, p.y);
+    du.cross(dv).normalize_or_zero()
+}
+
+//the largest distance of a control point to the flat triangle spanned by the corners, at its barycentric coordinates
+pub fn net_flatness(points: &[Vec3], degree: usize) -> f32 {
+    let corners = [0, degree, points.len() - 1].map(|i| points[i]);
+    let mut distance = 0f32;
+    for j in 0..degree + 1 {
+        for i in 0..degree + 1 - j {
+            let p = Vec2::new(i as f32, j as f32) / (degree.max(1) as f32);
+            let flat = interpolate(&corners, p);
+            distance = distance.max(points[net_index(degree, i, j)].distance(flat));
+        }
+    }
+    distance
+}
+
+//the control points along a side of the unit square the patch is stitched as, see from_square
+pub fn net_boundary<T>(points: &[T], degree: usize, side: math::Side2D) -> Vec<T>
+where
+    T: Copy,
+{
+    match side {
+        math::Side2D::U0 => (0..degree + 1).map(|j| points[net_index(degree, 0, j)]).collect(),
+        math::Side2D::U1 => (0..degree + 1)
+            .map(|j| points[net_index(degree, degree - j, j)])
+            .collect(),
+        math::Side2D::V0 => points[..degree + 1].to_vec(),
+        math::Side2D::V1 => vec![points[points.len() - 1]],
+    }
+}
+
+//the control points, colors and alphas of a patch
+type Net = (Vec<Vec3>, [Color; 3], [f32; 3]);
+
+/*
+    reads the control points of a patch of the given degree row by row,
+    followed by the three corner colors if the patch is colored
+    colors are given as rgba, the alpha value is optional if it would be on the next line
+    uncolored patches get the default color
+*/
+pub fn read_net(
+    tokens: &mut TokenStream,
+    degree: usize,
+    colored: bool,
+    default_color: Color,
+) -> Result<Net, ParseError> {
+    let points = tokens.take(3 * math::triangular_number(degree + 1), "coordinate")?;
+    let mut color_values = Vec::new();
+    for _ in 0..if colored { 3 } else { 0 } {
+        let mut values = tokens.take(3, "color component")?;
+        values.extend(tokens.take_rest_of_line(1));
+        color_values.push(values);
+    }
+    let mut colors = [default_color; 3];
+    let mut alphas = [1.; 3];
+    for (i, color) in tokens::parse_colors(&color_values)?.into_iter().enumerate() {
+        colors[i] = color.truncate();
+        alphas[i] = color.w;
     }
-    pub fn subdivide(&self, u: f32, v: f32) -> (Self, Self) {
-        unimplemented!()
+    Ok((tokens::parse_vec3s(&points, "coordinate")?, colors, alphas))
+}
+
+/*
+    one step of the de casteljau algorithm, reduces a net of the given degree to one of degree - 1 at (u,v)
+    the reduced net overwrites the net from the front, this works since b_ij of the reduced net
+    is stored in front of the points b_ij, b_(i+1)j and b_i(j+1) of the net it is computed from
+*/
+fn de_casteljau_step<T>(net: &mut [T], degree: usize, p: Vec2)
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let w = 1. - p.x - p.y;
+    for j in 0..degree {
+        for i in 0..degree - j {
+            let b_u = net[net_index(degree, i + 1, j)];
+            let b_v = net[net_index(degree, i, j + 1)];
+            let b_w = net[net_index(degree, i, j)];
+            net[net_index(degree - 1, i, j)] = b_u * p.x + b_v * p.y + b_w * w;
+        }
     }
 }
 
+/*
+    The blossom of the patch, the de casteljau algorithm with a different parameter in each step
+    With all arguments equal to (u,v) this is the point at (u,v)
+    the net is used up by the algorithm, there is one argument for each degree
+*/
+fn blossom_in_place<T>(net: &mut [T], degree: usize, args: &[Vec2]) -> T
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    for (step, p) in args.iter().enumerate() {
+        de_casteljau_step(net, degree - step, *p);
+    }
+    net[0]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    //a quadratic patch over the unit triangle in the xy-plane with the height u * v
+    fn example_bezier_triangle() -> BezierTriangle<Vec3, 2> {
+        let pts = [
+            Vec3::new(0., 0., 0.),
+            Vec3::new(0.5, 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 0.5, 0.),
+            Vec3::new(0.5, 0.5, 0.5),
+            Vec3::new(0., 1., 0.),
+        ];
+        let three_colors = [Color::new(1., 0., 0.), Color::new(0., 1., 0.), Color::new(0., 0., 1.)];
+        BezierTriangle::<_, 2>::new(pts, three_colors)
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{} != {}", a, b);
+    }
+
     #[test]
     fn initialization() {
         let three_colors = [Color::new(1., 0., 0.), Color::new(0., 1., 0.), Color::new(0., 0., 1.)];
 
         let pts = [1., 2., 3., 4., 5., 6.];
         let t = BezierTriangle::<_, 2>::new(pts, three_colors);
+        assert_eq!(t.points()[net_index(2, 1, 1)], 5.);
+        assert_eq!(t.points()[net_index(2, 0, 2)], 6.);
+    }
+
+    #[test]
+    fn bezier_triangle_evaluate() {
+        let t = example_bezier_triangle();
+        //corners are interpolated
+        assert_eq!(t.evaluate(0., 0.), Vec3::ZERO);
+        assert_eq!(t.evaluate(1., 0.), Vec3::X);
+        assert_eq!(t.evaluate(0., 1.), Vec3::Y);
+        for (u, v) in [(0.2, 0.3), (0.5, 0.5), (0.1, 0.8)] {
+            assert_close(t.evaluate(u, v), Vec3::new(u, v, u * v));
+            let (_, du, dv) = t.evaluate_with_derivatives(u, v);
+            assert_close(du, Vec3::new(1., 0., v));
+            assert_close(dv, Vec3::new(0., 1., u));
+        }
+        assert_eq!(t.normal(0., 0.), Vec3::Z);
+    }
+
+    #[test]
+    fn cubic_evaluate() {
+        //the de casteljau steps in place agree with the sum of the control points weighted by bernstein polynomials
+        let mut pts = [Vec3::ZERO; 10];
+        for (k, p) in pts.iter_mut().enumerate() {
+            *p = Vec3::new(k as f32, (k * k) as f32 * 0.1, (k % 3) as f32);
+        }
+        let t = BezierTriangle::<Vec3, 3>::new(pts, [Color::ONE; 3]);
+        let factorial = |n: usize| (1..n + 1).product::<usize>() as f32;
+        for (u, v) in [(0.2f32, 0.3f32), (0.6, 0.1), (0., 0.5)] {
+            let w = 1. - u - v;
+            let mut sum = Vec3::ZERO;
+            for j in 0..4 {
+                for i in 0..4 - j {
+                    let k = 3 - i - j;
+                    let weight = factorial(3) / (factorial(i) * factorial(j) * factorial(k));
+                    sum += pts[net_index(3, i, j)] * weight * u.powi(i as i32) * v.powi(j as i32) * w.powi(k as i32);
+                }
+            }
+            assert_close(t.evaluate(u, v), sum);
+            assert_close(t.evaluate_with_derivatives(u, v).0, sum);
+        }
+    }
+
+    #[test]
+    fn bezier_triangle_subdivide() {
+        let t = example_bezier_triangle();
+        let pieces = t.subdivide_midpoints();
+        assert_eq!(pieces.len(), 4);
+        //each piece agrees with the original patch on its part of the parameter triangle
+        let corners = [
+            [(0., 0.), (0.5, 0.), (0., 0.5)],
+            [(0.5, 0.), (1., 0.), (0.5, 0.5)],
+            [(0., 0.5), (0.5, 0.5), (0., 1.)],
+            [(0.5, 0.5), (0., 0.5), (0.5, 0.)],
+        ];
+        for (piece, [a, b, c]) in pieces.iter().zip(corners) {
+            let (a, b, c) = (Vec2::from(a), Vec2::from(b), Vec2::from(c));
+            for (u, v) in [(0., 0.), (0.3, 0.3), (1., 0.), (0.2, 0.7)] {
+                let p = a + (b - a) * u + (c - a) * v;
+                assert_close(piece.evaluate(u, v), t.evaluate(p.x, p.y));
+            }
+        }
+        //the middle piece is not flipped and gets interpolated colors and texture coordinates
+        assert!(pieces[3].normal(0.3, 0.3).z > 0.);
+        assert_close(pieces[3].colors()[0], Color::new(0., 0.5, 0.5));
+        assert_eq!(pieces[3].to_triangles()[0].uvs()[2], Vec2::new(0.5, 0.));
+        assert!(pieces.iter().all(|p| p.flatness() < t.flatness()));
+    }
+
+    #[test]
+    fn parse_bezier_triangle() {
+        let cbbp = "0 0 0\n1 0 0\n2 0 0\n3 0 0\n0 1 0\n1 1 1\n2 1 0\n0 2 0\n1 2 0\n0 3 0\n1 0 0 0.5\n0 1 0\n0 0 1";
+        let t = BezierTriangle::<Vec3, 3>::read(&mut TokenStream::new(cbbp), true, Color::ONE).unwrap();
+        assert_eq!(t.evaluate(0., 1.), Vec3::new(0., 3., 0.));
+        assert_eq!(t.colors()[1], Color::new(0., 1., 0.));
+        let triangle = &t.to_triangles()[0];
+        assert_eq!(
+            triangle.points(),
+            [Vec3::ZERO, Vec3::new(3., 0., 0.), Vec3::new(0., 3., 0.)]
+        );
+        assert_eq!(triangle.alphas(), [0.5, 1., 1.]);
+
+        //without colors the default color is used
+        let bbp = &cbbp[..cbbp.find("1 0 0 0.5").unwrap()];
+        let t = BezierTriangle::<Vec3, 3>::read(&mut TokenStream::new(bbp), false, Color::ONE).unwrap();
+        assert_eq!(t.colors(), [Color::ONE; 3]);
+        let e = BezierTriangle::<Vec3, 3>::read(&mut TokenStream::new(bbp), true, Color::ONE).unwrap_err();
+        assert_eq!(e.reason, "missing color component, 3 values expected but 0 found");
     }
 }
@@ -1,268 +1,1969 @@
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::fmt;
+use std::fs;
 use std::path::Path;
 
-use crate::bezier::rectangle::{BezierRectangle, FromString};
-use crate::subdivision::SubdivisionSet;
-use crate::triangle::{ToTriangle, Triangle};
-use bevy::prelude::{Mesh, Vec3};
+use crate::bezier::bspline::BSplineSurface;
+use crate::bezier::dyn_curve::DynBezierCurve;
+use crate::bezier::dyn_rectangle::DynBezierRectangle;
+use crate::bezier::dyn_triangle::DynBezierTriangle;
+use crate::bezier::rectangle::NetFormat;
+use crate::error::ParseError;
+use crate::math::Transform;
+use crate::segment::{Segment, ToSegments};
+use crate::stitching::{self, StitchSet, Surface};
+use crate::subdivision::{Flatness, SubdivisionSet, ADAPTIVE_ELEMENT_LIMIT, ELEMENT_LIMIT};
+use crate::tokens::{self, Token, TokenStream};
+use crate::triangle::Triangle;
+use crate::triangulation;
+use bevy::prelude::{Mat4, Mesh, Vec2, Vec3, Vec4};
+use std::ops::{Add, Mul};
 use Vec3 as Color;
 
 /*
-    for now, building and parsing is tedious. Because loaded objects have their own types,
-    we cannot create an array that contains all of them.
+    loaded objects have their own types, so they can not be kept in one array.
     using Vec<dyn Subdivide> seems like a possibility, but is not immediately possible.
-    This leads to some unwanted code duplication in build_objects and build_meshes
+    Instead Objects has one Vec for each type, rectangles of any degree are DynBezierRectangles
 */
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OffType {
+    Off(OffFormat),
+    //a bezier patch of degree n in u and m in v direction, rational if the format says so
+    Rect(usize, usize, NetFormat),
+    //a triangular bezier patch of the given degree, with corner colors if true
+    Tri(usize, bool),
+    //a bezier curve of the given degree, with colors at its ends if true
+    Curve(usize, bool),
+    //a B-spline surface of degree n in u and m in v direction, rational if the format says so
+    BSpline(usize, usize, NetFormat),
+    Mesh(MeshFormat),
+}
+
+fn match_header(keyword: &str) -> Option<OffType> {
+    //an ST prefix announces texture coordinates
+    let (textured, rest) = match keyword.strip_prefix("ST") {
+        Some(rest) => (true, rest),
+        None => (false, keyword),
+    };
+    //everything else is named like OFF sections, whose prefix letters are handled by off_format
+    if let Some(format) = off_format(rest) {
+        return Some(OffType::Off(OffFormat {
+            textured: textured,
+            ..format
+        }));
+    }
+    //meshes announce texture coordinates with their own U prefix
+    if let (Some(format), false) = (mesh_format(rest), textured) {
+        return Some(OffType::Mesh(format));
+    }
+    //a C prefix announces the corner colors of bezier patches and B-spline surfaces and the end colors of curves
+    let (colored, rest) = match rest.strip_prefix('C') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    if let Some((n, m, dimension)) = rect_degree(rest) {
+        let format = NetFormat {
+            textured: textured,
+            rational: dimension == 4,
+            colored: colored,
+        };
+        return Some(OffType::Rect(n, m, format));
+    }
+    //triangular patches have no texture coordinates
+    if let (Some(n), false) = (triangle_degree(rest), textured) {
+        return Some(OffType::Tri(n, colored));
+    }
+    //as in Geomview, BBP is another name for BEZ333
+    if rest == "BBP" {
+        let format = NetFormat {
+            textured: textured,
+            rational: false,
+            colored: colored,
+        };
+        return Some(OffType::Rect(3, 3, format));
+    }
+    if let Some((n, m, dimension)) = bspline_degree(rest) {
+        let format = NetFormat {
+            textured: textured,
+            rational: dimension == 4,
+            colored: colored,
+        };
+        return Some(OffType::BSpline(n, m, format));
+    }
+    match (curve_degree(rest), textured) {
+        (Some(n), false) => Some(OffType::Curve(n, colored)),
+        _ => None,
+    }
+}
+
+//the kind of object a section header introduces, including its degree, eg. "bezier rectangle of degree 3x3"
+pub fn primitive_name(header: &str) -> Option<String> {
+    if header == OBJ_SECTION {
+        return Some(String::from("polygon"));
+    }
+    let rational = |format: NetFormat| if format.rational { "rational " } else { "" };
+    let name = match match_header(header)? {
+        OffType::Off(_) => String::from("polygon"),
+        OffType::Mesh(_) => String::from("mesh"),
+        OffType::Rect(n, m, format) => format!("{}bezier rectangle of degree {}x{}", rational(format), n, m),
+        OffType::Tri(n, _) => format!("bezier triangle of degree {}", n),
+        OffType::Curve(n, _) => format!("bezier curve of degree {}", n),
+        OffType::BSpline(n, m, format) => format!("{}B-spline surface of degree {}x{}", rational(format), n, m),
+    };
+    Some(name)
+}
+
+//the values given for each vertex of an OFF section besides its position
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct OffFormat {
+    colored: bool,
+    normals: bool,
+    textured: bool,
+}
+
+//the format of an OFF header without ST prefix, whose prefix letters may come in any order, eg. CNOFF and NCOFF are the same
+fn off_format(keyword: &str) -> Option<OffFormat> {
+    let mut format = OffFormat::default();
+    for c in keyword.strip_suffix("OFF")?.chars() {
+        let flag = match c {
+            'C' => &mut format.colored,
+            'N' => &mut format.normals,
+            _ => return None,
+        };
+        if *flag {
+            return None;
+        }
+        *flag = true;
+    }
+    Some(format)
+}
+
+//the values given for each vertex of a MESH and whether the grid is closed in u and v direction
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct MeshFormat {
+    colored: bool,
+    normals: bool,
+    textured: bool,
+    //only the z coordinate is given, x and y are the grid indices
+    heights: bool,
+    wrap_u: bool,
+    wrap_v: bool,
+}
+
+/*
+    the format of a MESH header, whose prefix letters may come in any order like the ones of off_format
+    U announces texture coordinates, Z heights, u and v wrap the grid in that direction
+    the texture coordinates may also be announced with UV, as in UVMESH
+*/
+fn mesh_format(keyword: &str) -> Option<MeshFormat> {
+    let mut format = MeshFormat::default();
+    for c in keyword.strip_suffix("MESH")?.replacen("UV", "U", 1).chars() {
+        let flag = match c {
+            'C' => &mut format.colored,
+            'N' => &mut format.normals,
+            'U' => &mut format.textured,
+            'Z' => &mut format.heights,
+            'u' => &mut format.wrap_u,
+            'v' => &mut format.wrap_v,
+            _ => return None,
+        };
+        if *flag {
+            return None;
+        }
+        *flag = true;
+    }
+    Some(format)
+}
+
+/*
+    the degrees (n, m) and the dimension d of a BEZnmd header for any single digit degrees, d is 3 or 4 for rational patches
+    the ST and C prefixes of the header are removed by match_header
+*/
+fn rect_degree(line: &str) -> Option<(usize, usize, usize)> {
+    net_degree(line.strip_prefix("BEZ")?)
+}
+
+//the degrees and dimension of a BSPnmd header for B-spline surfaces, like for BEZnmd but the degrees start at 1
+fn bspline_degree(line: &str) -> Option<(usize, usize, usize)> {
+    match net_degree(line.strip_prefix("BSP")?)? {
+        (n, m, d) if n > 0 && m > 0 => Some((n, m, d)),
+        _ => None,
+    }
+}
+
+//the degrees n, m and the dimension d given by the digits nmd of a header
+fn net_degree(digits: &str) -> Option<(usize, usize, usize)> {
+    let mut chars = digits.chars();
+    let n = chars.next()?.to_digit(10)? as usize;
+    let m = chars.next()?.to_digit(10)? as usize;
+    match (chars.next()?, chars.next()) {
+        ('3', None) => Some((n, m, 3)),
+        ('4', None) => Some((n, m, 4)),
+        _ => None,
+    }
+}
+
+//the degree n of a BEZn3 curve header without prefix, the degree is a single digit from 1 to 9
+fn curve_degree(line: &str) -> Option<usize> {
+    let digits = line.strip_prefix("BEZ")?;
+    let mut chars = digits.chars();
+    let n = chars.next()?.to_digit(10)? as usize;
+    match (chars.next()?, chars.next()) {
+        ('3', None) if n > 0 => Some(n),
+        _ => None,
+    }
+}
+
+/*
+    the degree n of a TBEZn3 header for triangular patches without prefix, the degree is a single digit from 1 to 9
+    TBEZ alone stands for cubic patches, since Geomview's BBP is taken by bicubic rectangular patches
+*/
+fn triangle_degree(line: &str) -> Option<usize> {
+    match line.strip_prefix('T')? {
+        "BEZ" => Some(3),
+        rest => curve_degree(rest),
+    }
+}
+
+/*
+    The read functions read the objects of a section from the values following its header
+    They return one result for each object, so that a broken object only affects itself
+*/
+
+fn read_rects(
+    tokens: &mut TokenStream,
+    n: usize,
+    m: usize,
+    format: NetFormat,
+    default_color: Color,
+) -> Vec<Result<DynBezierRectangle<Vec3>, ParseError>> {
+    let mut patches = Vec::new();
+    while tokens.at_value() {
+        patches.push(DynBezierRectangle::read(tokens, n, m, format, default_color));
+    }
+    patches
+}
+
+fn read_rational_rects(
+    tokens: &mut TokenStream,
+    n: usize,
+    m: usize,
+    format: NetFormat,
+    default_color: Color,
+) -> Vec<Result<DynBezierRectangle<Vec4>, ParseError>> {
+    let mut patches = Vec::new();
+    while tokens.at_value() {
+        patches.push(DynBezierRectangle::read_rational(tokens, n, m, format, default_color));
+    }
+    patches
+}
+
+/*
+    B-spline surfaces are split into their bezier patches right away,
+    each result holds the patches of one surface, see BSplineSurface::read
+*/
+fn read_bsplines(
+    tokens: &mut TokenStream,
+    n: usize,
+    m: usize,
+    format: NetFormat,
+    default_color: Color,
+) -> Vec<Result<Vec<DynBezierRectangle<Vec3>>, ParseError>> {
+    let mut surfaces = Vec::new();
+    while tokens.at_value() {
+        surfaces.push(BSplineSurface::read(tokens, n, m, format, default_color).map(|s| s.to_patches()));
+    }
+    surfaces
+}
+
+fn read_rational_bsplines(
+    tokens: &mut TokenStream,
+    n: usize,
+    m: usize,
+    format: NetFormat,
+    default_color: Color,
+) -> Vec<Result<Vec<DynBezierRectangle<Vec4>>, ParseError>> {
+    let mut surfaces = Vec::new();
+    while tokens.at_value() {
+        surfaces.push(BSplineSurface::read_rational(tokens, n, m, format, default_color).map(|s| s.to_patches()));
+    }
+    surfaces
+}
+
+fn read_tris(
+    tokens: &mut TokenStream,
+    degree: usize,
+    colored: bool,
+    default_color: Color,
+) -> Vec<Result<DynBezierTriangle<Vec3>, ParseError>> {
+    let mut patches = Vec::new();
+    while tokens.at_value() {
+        patches.push(DynBezierTriangle::read(tokens, degree, colored, default_color));
+    }
+    patches
+}
+
+fn read_curves(
+    tokens: &mut TokenStream,
+    degree: usize,
+    colored: bool,
+    default_color: Color,
+) -> Vec<Result<DynBezierCurve<Vec3>, ParseError>> {
+    let mut curves = Vec::new();
+    while tokens.at_value() {
+        curves.push(DynBezierCurve::read(tokens, degree, colored, default_color));
+    }
+    curves
+}
+
+//a vertex of an OFF section with its optional normal, rgba color and texture coordinates
+#[derive(Debug, Clone)]
+struct OffVertex {
+    position: Vec3,
+    normal: Option<Vec3>,
+    color: Option<Vec4>,
+    uv: Option<Vec2>,
+}
+
+type Vertex = Result<OffVertex, ParseError>;
+
+/*
+    reads the faces of an OFF section in the given format, each face is split into triangles
+    the vertices of a COFF section have rgba colors, but colors may be given after the vertices of plain OFF as well.
+    whether they are is decided by the number of values on the line of the first vertex
+    the vertices of an NOFF section have a normal between their position and their color,
+    the ones of an STOFF section texture coordinates after all other values
+    all values are taken before they are parsed, since all colors of the section, the ones of the vertices
+    and the ones of the faces, are either integers or floats, see tokens::integer_colors
+*/
+fn read_off(
+    tokens: &mut TokenStream,
+    format: OffFormat,
+    default_color: Color,
+) -> Vec<Result<Vec<Triangle<Vec3>>, ParseError>> {
+    //if the counts or vertices can not be read, the whole section counts as one broken object
+    let (vertex_values, num_faces) = match read_off_vertices(tokens, format) {
+        Ok(v) => v,
+        Err(e) => {
+            tokens.skip_values();
+            return vec![Err(e)];
+        }
+    };
+    let mut face_values = Vec::new();
+    let mut error = None;
+    for _ in 0..num_faces {
+        match read_face(tokens) {
+            Ok(face) => face_values.push(face),
+            Err(e) => {
+                error = Some(e);
+                tokens.skip_values();
+                break;
+            }
+        }
+    }
+    if error.is_none() && tokens.at_value() {
+        let t = tokens.peek().unwrap();
+        let reason = format!("more values than the {} faces declared", num_faces);
+        error = Some(ParseError::new(t.line, t.column, reason));
+        tokens.skip_values();
+    }
+
+    let vertex_colors = vertex_values.iter().flat_map(|[_, _, color, _]| color);
+    let face_colors = face_values.iter().flatten().flat_map(|face| &face.color);
+    let integers = tokens::integer_colors(vertex_colors.chain(face_colors));
+    let vertices: Vec<Vertex> = vertex_values
+        .iter()
+        .map(|[position, normal, color, uv]| read_vertex(position, normal, color, uv, integers))
+        .collect();
+    let mut faces: Vec<_> = face_values
+        .into_iter()
+        .map(|face| {
+            let face = face?;
+            //a single value would be an index into a colormap, which is not supported
+            let face_color = match face.color.len() {
+                3 | 4 => Some(tokens::parse_color(&face.color, integers)?),
+                _ => None,
+            };
+            triangles_from_face(&face.count, &face.indices, face_color, &vertices, default_color)
+        })
+        .collect();
+    faces.extend(error.map(Err));
+    faces
+}
+
+/*
+    reads the counts and vertex values of an OFF section, returns the position, normal, color and uv values
+    of each vertex, which are empty if the vertex has none, and the number of faces
+*/
+fn read_off_vertices(tokens: &mut TokenStream, format: OffFormat) -> Result<(Vec<[Vec<Token>; 4]>, usize), ParseError> {
+    let counts = tokens::parse_all::<usize>(&tokens.take(2, "vertex and face count")?, "count")?;
+    //the number of edges is not needed
+    tokens.take_rest_of_line(1);
+    let num_normal_values = if format.normals { 3 } else { 0 };
+    let num_uv_values = if format.textured { 2 } else { 0 };
+    let num_other_values = 3 + num_normal_values + num_uv_values;
+    let num_color_values = match tokens.line_length() {
+        Some(n) if n > num_other_values => (n - num_other_values).min(4),
+        _ if format.colored => 4,
+        _ => 0,
+    };
+    let mut vertices = Vec::new();
+    for _ in 0..counts[0] {
+        let values = tokens.take(num_other_values + num_color_values, "vertex value")?;
+        let (position, values) = values.split_at(3);
+        let (normal, values) = values.split_at(num_normal_values);
+        let (color, uv) = values.split_at(num_color_values);
+        vertices.push([position, normal, color, uv].map(|values| values.to_vec()));
+    }
+    Ok((vertices, counts[1]))
+}
+
+/*
+    parses the values of a vertex, normal, color and uv are empty if the vertex has none
+    a position with a single value is only the z coordinate, x and y are 0 then
+    integers tells whether the colors of the object the vertex belongs to are integers, see tokens::integer_colors
+*/
+fn read_vertex(position: &[Token], normal: &[Token], color: &[Token], uv: &[Token], integers: bool) -> Vertex {
+    let position = match position.len() {
+        1 => Vec3::new(0., 0., position[0].parse("coordinate")?),
+        _ => tokens::parse_vec3s(position, "coordinate")?[0],
+    };
+    let normal = match normal.len() {
+        0 => None,
+        _ => Some(tokens::parse_vec3s(normal, "normal component")?[0].normalize_or_zero()),
+    };
+    //a single color value would be an index into a colormap, which is not supported
+    let color = match color.len() {
+        3 | 4 => Some(tokens::parse_color(color, integers)?),
+        _ => None,
+    };
+    let uv = match uv.len() {
+        0 => None,
+        _ => {
+            let st = tokens::parse_all::<f32>(uv, "texture coordinate")?;
+            Some(Vec2::new(st[0], st[1]))
+        }
+    };
+    Ok(OffVertex {
+        position: position,
+        normal: normal,
+        color: color,
+        uv: uv,
+    })
+}
+
+/*
+    reads the grids of a MESH section, each grid is split into two triangles per quad
+    a grid starts with its size nu nv, followed by the nu * nv vertices row by row, ie. with u changing fastest
+    each vertex has its position, or only the z coordinate for heights, a normal, an rgba color
+    and texture coordinates (u v w) as the format says, of which w is ignored
+    a wrapped grid has quads between its last and its first row or column as well
+*/
+fn read_mesh(
+    tokens: &mut TokenStream,
+    format: MeshFormat,
+    default_color: Color,
+) -> Vec<Result<Vec<Triangle<Vec3>>, ParseError>> {
+    let mut grids = Vec::new();
+    while tokens.at_value() {
+        grids.push(read_grid(tokens, format, default_color));
+    }
+    grids
+}
+
+fn read_grid(
+    tokens: &mut TokenStream,
+    format: MeshFormat,
+    default_color: Color,
+) -> Result<Vec<Triangle<Vec3>>, ParseError> {
+    let size_tokens = tokens.take(2, "grid size")?;
+    let size = tokens::parse_all::<usize>(&size_tokens, "grid size")?;
+    let (nu, nv) = (size[0], size[1]);
+    let num_position_values = if format.heights { 1 } else { 3 };
+    let num_normal_values = if format.normals { 3 } else { 0 };
+    let num_color_values = if format.colored { 4 } else { 0 };
+    let num_uv_values = if format.textured { 3 } else { 0 };
+    let num_values = num_position_values + num_normal_values + num_color_values + num_uv_values;
+    let num_grid_values = nu
+        .checked_mul(nv)
+        .and_then(|n| n.checked_mul(num_values))
+        .ok_or_else(|| {
+            let t = &size_tokens[0];
+            ParseError::new(t.line, t.column, format!("grid size too large: {} x {}", nu, nv))
+        })?;
+    let values = tokens.take(num_grid_values, "vertex value")?;
+
+    //the colors of all vertices of a grid are either integers or floats
+    let color_start = num_position_values + num_normal_values;
+    let color_values = values
+        .chunks_exact(num_values)
+        .flat_map(|v| &v[color_start..color_start + num_color_values]);
+    let integers = tokens::integer_colors(color_values);
+    let mut vertices = Vec::with_capacity(nu * nv);
+    for (i, values) in values.chunks_exact(num_values).enumerate() {
+        let (position, values) = values.split_at(num_position_values);
+        let (normal, values) = values.split_at(num_normal_values);
+        let (color, uv) = values.split_at(num_color_values);
+        let mut vertex = read_vertex(position, normal, color, uv, integers)?;
+        if format.heights {
+            vertex.position += Vec3::new((i % nu) as f32, (i / nu) as f32, 0.);
+        }
+        if vertex.color.is_none() {
+            vertex.color = Some(default_color.extend(1.));
+        }
+        vertices.push(vertex);
+    }
+
+    //a grid with a single row or column has nothing to close, wrapping it would only add degenerate quads
+    let num_quads_u = if format.wrap_u && nu > 1 {
+        nu
+    } else {
+        nu.saturating_sub(1)
+    };
+    let num_quads_v = if format.wrap_v && nv > 1 {
+        nv
+    } else {
+        nv.saturating_sub(1)
+    };
+    let mut triangles = Vec::new();
+    for j in 0..num_quads_v {
+        for i in 0..num_quads_u {
+            let (i1, j1) = ((i + 1) % nu, (j + 1) % nv);
+            let quad = [(i, j), (i1, j), (i1, j1), (i, j1)].map(|(i, j)| vertices[j * nu + i].clone());
+            triangles.extend(polygon_triangles(&quad, None, default_color));
+        }
+    }
+    Ok(triangles)
+}
+
+//the values of an OFF face, its vertex count, the vertex indices and the values of its color after them
+struct OffFace {
+    count: Token,
+    indices: Vec<Token>,
+    color: Vec<Token>,
+}
+
+//reads the values of a face of an OFF section, the outer error means that the rest of the section can not be read
+fn read_face(tokens: &mut TokenStream) -> Result<Result<OffFace, ParseError>, ParseError> {
+    let count = tokens.take(1, "vertex count")?.remove(0);
+    let num_indices = match count.parse::<usize>("vertex count") {
+        Ok(n) => n,
+        Err(e) => {
+            //without a vertex count the face is assumed to end with its line
+            tokens.take_rest_of_line(usize::MAX);
+            return Ok(Err(e));
+        }
+    };
+    let indices = tokens.take(num_indices, "vertex index")?;
+    let color = tokens.take_rest_of_line(4);
+    Ok(Ok(OffFace {
+        count: count,
+        indices: indices,
+        color: color,
+    }))
+}
+
+/*
+    the triangles of an OFF face
+    a color given for the face is used for all of its triangles,
+    otherwise they get per-vertex colors if all vertices of the face have a color, or else the default color
+    the normals of the vertices are used if all of them have one, otherwise the triangles get flat normals,
+    the same holds for texture coordinates, which are 0 otherwise
+*/
+fn triangles_from_face(
+    count: &Token,
+    indices: &[Token],
+    face_color: Option<Vec4>,
+    vertices: &[Vertex],
+    default_color: Color,
+) -> Result<Vec<Triangle<Vec3>>, ParseError> {
+    if indices.len() < 3 {
+        let reason = format!("a face needs at least 3 vertices, {} given", indices.len());
+        return Err(ParseError::new(count.line, count.column, reason));
+    }
+    let mut polygon = Vec::with_capacity(indices.len());
+    for index in indices {
+        let i = index.parse::<usize>("vertex index")?;
+        let vertex = vertices.get(i).ok_or_else(|| {
+            let reason = format!("vertex index {} out of range, {} vertices declared", i, vertices.len());
+            ParseError::new(index.line, index.column, reason)
+        })?;
+        polygon.push(vertex.clone()?);
+    }
+    Ok(polygon_triangles(&polygon, face_color, default_color))
+}
+
+//splits a polygon into triangles, which get their colors, normals and texture coordinates as described for triangles_from_face
+fn polygon_triangles(polygon: &[OffVertex], face_color: Option<Vec4>, default_color: Color) -> Vec<Triangle<Vec3>> {
+    let positions: Vec<Vec3> = polygon.iter().map(|v| v.position).collect();
+    let colors: Vec<Vec4> = match (face_color, polygon.iter().all(|v| v.color.is_some())) {
+        (Some(color), _) => vec![color; polygon.len()],
+        (None, true) => polygon.iter().map(|v| v.color.unwrap()).collect(),
+        (None, false) => vec![default_color.extend(1.); polygon.len()],
+    };
+    let normals: Option<Vec<Vec3>> = polygon.iter().map(|v| v.normal).collect();
+    let uvs: Option<Vec<Vec2>> = polygon.iter().map(|v| v.uv).collect();
+    triangulation::triangulate(&positions)
+        .into_iter()
+        .map(|[a, b, c]| {
+            let points = [positions[a], positions[b], positions[c]];
+            let [ca, cb, cc] = [colors[a], colors[b], colors[c]];
+            let rgb = [ca.truncate(), cb.truncate(), cc.truncate()];
+            let triangle = match &normals {
+                Some(n) => Triangle::new_with_normals(points, rgb, [n[a], n[b], n[c]]),
+                None => Triangle::new(points, rgb),
+            };
+            let triangle = triangle.with_alphas([ca.w, cb.w, cc.w]);
+            match &uvs {
+                Some(st) => triangle.with_uvs([st[a], st[b], st[c]]),
+                None => triangle,
+            }
+        })
+        .collect()
+}
+
+/*
+    reads the faces of a Wavefront OBJ file, which unlike OFF is line based
+    v lines are positions with an optional w or an rgb(a) color, vn lines normals, vt lines texture coordinates and
+    each f line is a polygon of vertices given as position, position/uv, position//normal or position/uv/normal indices
+    indices start at 1, negative ones count back from the last element read so far
+    all other lines, eg. groups, materials or lines, are ignored
+*/
+fn read_obj(input: &str, default_color: Color) -> Vec<Result<Vec<Triangle<Vec3>>, ParseError>> {
+    let mut positions = Vec::<Vertex>::new();
+    let mut normals = Vec::<Result<Vec3, ParseError>>::new();
+    let mut uvs = Vec::<Result<Vec2, ParseError>>::new();
+    let mut faces = Vec::new();
+    for line in tokens::tokenize(input).chunk_by(|a, b| a.line == b.line) {
+        let (keyword, values) = line.split_first().unwrap();
+        match keyword.text.as_str() {
+            "v" => positions.push(read_obj_vertex(keyword, values)),
+            "vn" => normals.push(
+                read_obj_values(keyword, values, 3, "normal component")
+                    .map(|n| Vec3::new(n[0], n[1], n[2]).normalize_or_zero()),
+            ),
+            //the v coordinate is optional, a w coordinate is ignored
+            "vt" => uvs.push(
+                read_obj_values(keyword, &values[..values.len().min(2)], 1, "texture coordinate")
+                    .map(|st| Vec2::new(st[0], st.get(1).copied().unwrap_or(0.))),
+            ),
+            "f" => {
+                let face = read_obj_face(keyword, values, &positions, &normals, &uvs, default_color);
+                faces.push(face);
+            }
+            _ if keyword.is_keyword() => {}
+            _ => {
+                let reason = String::from("values without a keyword");
+                faces.push(Err(ParseError::new(keyword.line, keyword.column, reason)));
+            }
+        }
+    }
+    faces
+}
+
+//parses the values after a keyword, at least min of them
+fn read_obj_values(keyword: &Token, values: &[Token], min: usize, what: &str) -> Result<Vec<f32>, ParseError> {
+    if values.len() < min {
+        let reason = format!("missing {}, {} values expected but {} found", what, min, values.len());
+        return Err(ParseError::new(keyword.line, keyword.column, reason));
+    }
+    tokens::parse_all::<f32>(values, what)
+}
+
+/*
+    a position x y z, followed either by a weight w or by an rgb or rgba color
+    the weight only matters for rational curves and surfaces, which are not read, so it is ignored
+    unlike in OFF files the color values are always floats between 0 and 1
+*/
+fn read_obj_vertex(keyword: &Token, values: &[Token]) -> Vertex {
+    match values.len() {
+        3 | 4 => read_vertex(&values[..3], &[], &[], &[], false),
+        6 | 7 => {
+            let mut vertex = read_vertex(&values[..3], &[], &[], &[], false)?;
+            vertex.color = Some(tokens::parse_float_color(&values[3..])?);
+            Ok(vertex)
+        }
+        n => {
+            let reason = format!(
+                "a vertex needs 3 coordinates and optionally a weight or a color, {} values given",
+                n
+            );
+            Err(ParseError::new(keyword.line, keyword.column, reason))
+        }
+    }
+}
+
+//reads a face of an OBJ file, its vertices get the normals and texture coordinates given with their positions
+fn read_obj_face(
+    keyword: &Token,
+    values: &[Token],
+    positions: &[Vertex],
+    normals: &[Result<Vec3, ParseError>],
+    uvs: &[Result<Vec2, ParseError>],
+    default_color: Color,
+) -> Result<Vec<Triangle<Vec3>>, ParseError> {
+    if values.len() < 3 {
+        let reason = format!("a face needs at least 3 vertices, {} given", values.len());
+        return Err(ParseError::new(keyword.line, keyword.column, reason));
+    }
+    let mut polygon = Vec::with_capacity(values.len());
+    for value in values {
+        let mut indices = value.text.split('/');
+        let mut vertex = obj_element(value, indices.next().unwrap(), positions, "vertex")?.clone()?;
+        if let Some(index) = indices.next().filter(|i| !i.is_empty()) {
+            vertex.uv = Some(obj_element(value, index, uvs, "texture coordinate")?.clone()?);
+        }
+        if let Some(index) = indices.next() {
+            vertex.normal = Some(obj_element(value, index, normals, "normal")?.clone()?);
+        }
+        polygon.push(vertex);
+    }
+    Ok(polygon_triangles(&polygon, None, default_color))
+}
+
+//the element an index of a face refers to, value is the vertex of the face the index belongs to
+fn obj_element<'a, T>(value: &Token, index: &str, elements: &'a [T], what: &str) -> Result<&'a T, ParseError> {
+    let error = |reason: String| ParseError::new(value.line, value.column, reason);
+    let i = index
+        .parse::<isize>()
+        .map_err(|_| error(format!("invalid {} index '{}'", what, value.text)))?;
+    //indices start at 1, so 0 is never valid
+    let position = match i {
+        i if i > 0 => Some(i as usize - 1),
+        i => elements.len().checked_sub(i.unsigned_abs()).filter(|_| i != 0),
+    };
+    position.and_then(|p| elements.get(p)).ok_or_else(|| {
+        error(format!(
+            "{} index {} out of range, {} given so far",
+            what,
+            i,
+            elements.len()
+        ))
+    })
+}
+
+//whether a file is read as Wavefront OBJ instead of OFF, which is decided by its extension
+fn is_obj_file(path: &Path) -> bool {
+    path.extension().map_or(false, |e| e.eq_ignore_ascii_case("obj"))
+}
+
+//the section of the report that lists the faces of an OBJ file
+const OBJ_SECTION: &str = "OBJ";
+
+//adds the objects that could be read to objects, returns how many these were and the errors of the others
+fn collect<T>(results: Vec<Result<T, ParseError>>, objects: &mut Vec<T>) -> (usize, Vec<ParseError>) {
+    let mut loaded = 0;
+    let mut skipped = Vec::new();
+    for result in results {
+        match result {
+            Ok(object) => {
+                objects.push(object);
+                loaded += 1;
+            }
+            Err(e) => skipped.push(e),
+        }
+    }
+    (loaded, skipped)
+}
+
+//the number of objects that were loaded and the errors of the skipped ones for each kind of section, in order of appearance
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    pub sections: Vec<SectionReport>,
+}
+
+#[derive(Debug)]
+pub struct SectionReport {
+    pub header: String,
+    pub loaded: usize,
+    pub skipped: Vec<ParseError>,
+}
+
+impl ParseReport {
+    fn add(&mut self, header: &str, loaded: usize, skipped: Vec<ParseError>) {
+        match self.sections.iter_mut().find(|s| s.header == header) {
+            Some(section) => {
+                section.loaded += loaded;
+                section.skipped.extend(skipped);
+            }
+            None => self.sections.push(SectionReport {
+                header: header.to_string(),
+                loaded: loaded,
+                skipped: skipped,
+            }),
+        }
+    }
+
+    pub fn num_skipped(&self) -> usize {
+        self.sections.iter().map(|s| s.skipped.len()).sum()
+    }
+
+    //the number of loaded objects of each kind, eg. CBEZ333 and BEZ333 sections both contain bicubic patches
+    pub fn primitives(&self) -> Vec<(String, usize)> {
+        let mut primitives = Vec::<(String, usize)>::new();
+        for section in &self.sections {
+            let name = match primitive_name(&section.header) {
+                Some(name) => name,
+                None => continue,
+            };
+            match primitives.iter_mut().find(|p| p.0 == name) {
+                Some(p) => p.1 += section.loaded,
+                None => primitives.push((name, section.loaded)),
+            }
+        }
+        primitives
+    }
+}
+
+impl fmt::Display for ParseReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for section in &self.sections {
+            writeln!(
+                f,
+                "{}: {} loaded, {} skipped",
+                section.header,
+                section.loaded,
+                section.skipped.len()
+            )?;
+            for e in &section.skipped {
+                writeln!(f, "    {}", e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/*
+    subdivides each patch either uniformly into at least limit pieces or, if a tolerance is given,
+    until each piece is flat enough or the patch has adaptive_limit pieces, and adds it to the stitch set,
+    T is Vec4 for rational patches
+*/
+fn add_patches<'a, T>(
+    stitch: &mut StitchSet<'a>,
+    patches: &'a Vec<DynBezierRectangle<T>>,
+    limit: usize,
+    adaptive_limit: usize,
+    tolerance: Option<f32>,
+) where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T> + std::fmt::Debug,
+    DynBezierRectangle<T>: Surface + Flatness,
+{
+    for patch in patches {
+        let mut subdiv = SubdivisionSet::new();
+        subdiv.elements = vec![patch.clone()];
+        match tolerance {
+            Some(t) => subdiv.subdivide_adaptive_until(t, adaptive_limit),
+            None => subdiv.subdivide_until(limit),
+        }
+        stitch.add(patch, subdiv.elements.iter().map(|e| e.domain()).collect());
+    }
+}
+
+/*
+    adds triangular patches to the stitch set, each one is split into the same number of pieces as with add_patches,
+    but uniformly, since the pieces are the ones of the unit square the patch is stitched as, see DynBezierTriangle
+    with a tolerance the patch is split until all parts of the parameter triangle are flat enough
+*/
+fn add_triangular_patches<'a>(
+    stitch: &mut StitchSet<'a>,
+    patches: &'a Vec<DynBezierTriangle<Vec3>>,
+    limit: usize,
+    adaptive_limit: usize,
+    tolerance: Option<f32>,
+) {
+    for patch in patches {
+        let depth = match tolerance {
+            Some(t) => {
+                let mut subdiv = SubdivisionSet::new();
+                subdiv.elements = vec![patch.clone()];
+                subdiv.subdivide_uniform_adaptive_until(t, adaptive_limit);
+                //each step splits every part into four
+                subdiv.elements.len().trailing_zeros() / 2
+            }
+            None => (0..).find(|d| 4usize.pow(*d) >= limit).unwrap(),
+        };
+        stitch.add(patch, stitching::uniform_domains(depth));
+    }
+}
+
+//where the tokens being read come from, includes are resolved relative to the file and nested at most MAX_INCLUDE_DEPTH deep
 #[derive(Debug, Clone, Copy)]
-enum LineType {
-    Header(OffType),
-    Values,
+struct Source<'a> {
+    file: &'a str,
+    depth: usize,
 }
 
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+//the objects read so far, one Vec for each type
+#[derive(Default)]
+struct Objects {
+    //polygons and grids, already split into triangles
+    triangles: Vec<Triangle<Vec3>>,
+    //bezier rectangles of any degree and the pieces of B-spline surfaces
+    rectangles: Vec<DynBezierRectangle<Vec3>>,
+    //rational bezier rectangles and B-spline surfaces, with homogeneous control points
+    rational_rectangles: Vec<DynBezierRectangle<Vec4>>,
+    //triangular bezier patches of any degree
+    triangular_patches: Vec<DynBezierTriangle<Vec3>>,
+    curves: Vec<DynBezierCurve<Vec3>>,
+}
+
+//the number of objects of each type, see Objects::transform_since
 #[derive(Debug, Clone, Copy)]
-enum OffType {
-    None,
-    Off,
-    Rect11,
-    Rect22,
-    Rect33,
-    Rect44,
+struct ObjectCounts {
+    triangles: usize,
+    rectangles: usize,
+    rational_rectangles: usize,
+    triangular_patches: usize,
+    curves: usize,
 }
 
-fn offtype_id(offtype: &OffType) -> usize {
-    match offtype {
-        OffType::None => 0,
-        OffType::Off => 1,
-        OffType::Rect11 => 2,
-        OffType::Rect22 => 3,
-        OffType::Rect33 => 4,
-        OffType::Rect44 => 5,
+impl Objects {
+    fn counts(&self) -> ObjectCounts {
+        ObjectCounts {
+            triangles: self.triangles.len(),
+            rectangles: self.rectangles.len(),
+            rational_rectangles: self.rational_rectangles.len(),
+            triangular_patches: self.triangular_patches.len(),
+            curves: self.curves.len(),
+        }
+    }
+
+    //transforms all objects that were added after the counts were taken
+    fn transform_since(&mut self, counts: ObjectCounts, m: Mat4) {
+        transform_all(&mut self.triangles[counts.triangles..], m);
+        transform_all(&mut self.rectangles[counts.rectangles..], m);
+        transform_all(&mut self.rational_rectangles[counts.rational_rectangles..], m);
+        transform_all(&mut self.triangular_patches[counts.triangular_patches..], m);
+        transform_all(&mut self.curves[counts.curves..], m);
+    }
+
+    //the number of patches the ELEMENT_LIMIT is shared by
+    fn num_patches(&self) -> usize {
+        self.rectangles.len() + self.rational_rectangles.len() + self.triangular_patches.len()
     }
 }
 
-fn line_length(offtype: OffType) -> usize {
-    match offtype {
-        OffType::None => 0,
-        OffType::Off => 1,
-        OffType::Rect11 => 2 * 2 + 4,
-        OffType::Rect22 => 3 * 3 + 4,
-        OffType::Rect33 => 4 * 4 + 4,
-        OffType::Rect44 => 5 * 5 + 4,
+fn transform_all<T: Transform>(objects: &mut [T], m: Mat4) {
+    for object in objects {
+        object.transform(m);
     }
 }
 
-fn match_line_type(line: &String) -> LineType {
-    match line.as_str() {
-        "OFF" | "COFF" => LineType::Header(OffType::Off),
-        "CBEZ113" => LineType::Header(OffType::Rect11),
-        "CBEZ223" => LineType::Header(OffType::Rect22),
-        "CBEZ333" => LineType::Header(OffType::Rect33),
-        "CBEZ443" => LineType::Header(OffType::Rect44),
-        _ => LineType::Values,
+/*
+    reads the 16 values of a 4x4 matrix, optionally enclosed in braces
+    like in Geomview the matrix is given row by row and applied to row vectors, ie. its last row is the translation,
+    which are exactly the columns of the matrix that is applied to column vectors
+*/
+fn read_transform(tokens: &mut TokenStream) -> Result<Mat4, ParseError> {
+    let braces = tokens.peek().map_or(false, |t| t.text == "{");
+    if braces {
+        tokens.next();
     }
+    let values = tokens::parse_all::<f32>(&tokens.take(16, "matrix entry")?, "matrix entry")?;
+    if braces {
+        match tokens.next() {
+            Some(t) if t.text == "}" => {}
+            Some(t) => {
+                return Err(ParseError::new(
+                    t.line,
+                    t.column,
+                    format!("expected '}}' after the matrix, found '{}'", t.text),
+                ))
+            }
+            None => return Err(ParseError::new(0, 0, String::from("expected '}' after the matrix"))),
+        }
+    }
+    Ok(Mat4::from_cols_array(&values.try_into().unwrap()))
 }
 
+//number of segments a curve is split into when it is subdivided uniformly
+const CURVE_SEGMENTS: usize = 64;
+
+//the largest subdivision depth that can be chosen, also for adaptive subdivision, a patch subdivided this deep has 4^8 = 65536 pieces
+pub const MAX_DEPTH: u32 = 8;
+
 pub struct MeshBuilder {
-    strings: [String; 6],
-    objects: (
-        (),
-        Vec<Triangle<Vec3>>,
-        Vec<BezierRectangle<Vec3, 1, 1>>,
-        Vec<BezierRectangle<Vec3, 2, 2>>,
-        Vec<BezierRectangle<Vec3, 3, 3>>,
-        Vec<BezierRectangle<Vec3, 4, 4>>,
-    ),
-    default_color: Color
+    objects: Objects,
+    default_color: Color,
+    tolerance: Option<f32>,
+    //number of uniform subdivision steps of each patch and curve, instead of subdividing all of them into ELEMENT_LIMIT pieces
+    depth: Option<u32>,
+    //abort on the first object that can not be read instead of skipping it
+    strict: bool,
 }
 
 impl MeshBuilder {
-    pub fn new(default_color: Color) -> Self {
+    pub fn new(default_color: Color, tolerance: Option<f32>, strict: bool) -> Self {
         MeshBuilder {
-            strings: [String::new(), String::new(), String::new(), String::new(), String::new(), String::new()],
-            objects: (
-                (),
-                Vec::<Triangle<Vec3>>::new(),
-                Vec::<BezierRectangle<Vec3, 1, 1>>::new(),
-                Vec::<BezierRectangle<Vec3, 2, 2>>::new(),
-                Vec::<BezierRectangle<Vec3, 3, 3>>::new(),
-                Vec::<BezierRectangle<Vec3, 4, 4>>::new(),
-            ),
+            objects: Objects::default(),
             default_color: default_color,
+            tolerance: tolerance,
+            depth: None,
+            strict: strict,
         }
     }
 
-    fn build_objects(&mut self) {
-        {&mut self.build_triangles()};
-        {&mut self.build_rect11()};
-        {&mut self.build_rect22()};
-        {&mut self.build_rect33()};
-        {&mut self.build_rect44()};
+    /*
+        subdivides each patch depth times uniformly, ie. into 4^depth pieces, and each curve into 2^depth segments,
+        the depth is limited to MAX_DEPTH, a tolerance takes precedence over it
+    */
+    pub fn with_depth(mut self, depth: u32) -> Self {
+        self.depth = Some(depth.min(MAX_DEPTH));
+        self
     }
 
-    fn build_rect11(&mut self) {
-        let mut input = String::new();
-        let mut i = 0;
-        for line in self.strings[2].lines() {
-            i += 1;
-            input.push_str(&line);
-            input.push_str("\n");
-            if i == line_length(OffType::Rect11) {
-                if let Ok(surf) = BezierRectangle::<Vec3, 1, 1>::from_string(&input) {
-                    self.objects.2.push(surf);
-                }
-                input.clear();
-                i = 0;
-            }
+    //reads the faces of an OBJ file, the report lists all of them in one section
+    fn read_obj_file(&mut self, input: &str, file: &str) -> Result<ParseReport, ParseError> {
+        let mut report = ParseReport::default();
+        let mut faces = Vec::new();
+        let (loaded, skipped) = collect(read_obj(input, self.default_color), &mut faces);
+        self.objects.triangles.extend(faces.into_iter().flatten());
+        let source = Source { file: file, depth: 0 };
+        self.add_to_report(&mut report, OBJ_SECTION, source, loaded, skipped)?;
+        Ok(report)
+    }
+
+    //reads all items of the input, see parse_file
+    fn read_objects(&mut self, tokens: &mut TokenStream, file: &str) -> Result<ParseReport, ParseError> {
+        let mut report = ParseReport::default();
+        let source = Source { file: file, depth: 0 };
+        while tokens.peek().is_some() {
+            self.read_item(tokens, source, &mut report)?;
         }
+        Ok(report)
     }
 
-    fn build_rect22(&mut self) {
-        let mut input = String::new();
-        let mut i = 0;
-        for line in self.strings[3].lines() {
-            i += 1;
-            input.push_str(&line);
-            input.push_str("\n");
-            if i == line_length(OffType::Rect22) {
-                if let Ok(surf) = BezierRectangle::<Vec3, 2, 2>::from_string(&input) {
-                    self.objects.3.push(surf);
-                }
-                input.clear();
-                i = 0;
+    /*
+        reads one item of a scene, which is a section, a group in braces, an instance or an include
+        a LIST only marks its group as a list of objects, so its objects are simply the items after it
+        the error is only returned in strict mode, otherwise errors are added to the report
+    */
+    fn read_item(
+        &mut self,
+        tokens: &mut TokenStream,
+        source: Source,
+        report: &mut ParseReport,
+    ) -> Result<(), ParseError> {
+        let token = tokens.next().unwrap();
+        match token.text.as_str() {
+            "{" => self.read_group(&token, tokens, source, report),
+            "<" => self.read_include(&token, tokens, source, report),
+            "INST" => self.read_inst(tokens, source, report),
+            "LIST" => Ok(()),
+            "}" => {
+                let e = ParseError::new(token.line, token.column, String::from("'}' without matching '{'"));
+                self.add_to_report(report, "}", source, 0, vec![e])
             }
+            _ => self.read_section(token, tokens, source, report),
         }
     }
-    fn build_rect33(&mut self) {
-        let mut input = String::new();
-        let mut i = 0;
-        for line in self.strings[4].lines() {
-            i += 1;
-            input.push_str(&line);
-            input.push_str("\n");
-            if i == line_length(OffType::Rect33) {
-                if let Ok(surf) = BezierRectangle::<Vec3, 3, 3>::from_string(&input) {
-                    self.objects.4.push(surf);
+
+    //reads the items of a group up to its closing brace, open is the opening one
+    fn read_group(
+        &mut self,
+        open: &Token,
+        tokens: &mut TokenStream,
+        source: Source,
+        report: &mut ParseReport,
+    ) -> Result<(), ParseError> {
+        loop {
+            match tokens.peek().map(|t| t.text.as_str()) {
+                Some("}") => {
+                    tokens.next();
+                    return Ok(());
+                }
+                Some(_) => self.read_item(tokens, source, report)?,
+                None => {
+                    let e = ParseError::new(open.line, open.column, String::from("'{' without matching '}'"));
+                    return self.add_to_report(report, "{", source, 0, vec![e]);
                 }
-                input.clear();
-                i = 0;
             }
         }
     }
 
-    fn build_rect44(&mut self) {
-        let mut input = String::new();
-        let mut i = 0;
-        for line in self.strings[5].lines() {
-            i += 1;
-            input.push_str(&line);
-            if i == line_length(OffType::Rect44) {
-                if let Ok(surf) = BezierRectangle::<Vec3, 4, 4>::from_string(&input) {
-                    self.objects.5.push(surf);
+    /*
+        reads the transform and geom clauses of an INST, in any order, up to the end of its group
+        the transform is a 4x4 matrix, which may be enclosed in braces, see read_transform
+        all objects of the geom are transformed by it once the INST is complete, so nested instances apply their
+        own transform first
+    */
+    fn read_inst(
+        &mut self,
+        tokens: &mut TokenStream,
+        source: Source,
+        report: &mut ParseReport,
+    ) -> Result<(), ParseError> {
+        let counts = self.objects.counts();
+        let mut transform = Mat4::IDENTITY;
+        loop {
+            match tokens.peek().map(|t| t.text.as_str()) {
+                Some("transform") => {
+                    tokens.next();
+                    match read_transform(tokens) {
+                        Ok(m) => transform = m,
+                        Err(e) => self.add_to_report(report, "INST", source, 0, vec![e])?,
+                    }
                 }
-                input.clear();
-                i = 0;
+                Some("geom") => {
+                    tokens.next();
+                    if tokens.peek().is_some() {
+                        self.read_item(tokens, source, report)?;
+                    }
+                }
+                _ => break,
             }
         }
+        self.transform_objects(counts, transform);
+        Ok(())
     }
 
-    fn build_triangles(&mut self) {
-        if self.strings[1].is_empty() {
-            return;
+    //reads the file named after <, relative to the directory of the file that includes it
+    fn read_include(
+        &mut self,
+        include: &Token,
+        tokens: &mut TokenStream,
+        source: Source,
+        report: &mut ParseReport,
+    ) -> Result<(), ParseError> {
+        //the name is taken as it is, even if it starts with a letter
+        let name = match tokens.next() {
+            Some(t) => t.text,
+            None => {
+                let e = ParseError::new(
+                    include.line,
+                    include.column,
+                    String::from("missing file name after '<'"),
+                );
+                return self.add_to_report(report, "<", source, 0, vec![e]);
+            }
+        };
+        if source.depth >= MAX_INCLUDE_DEPTH {
+            let reason = format!("includes are nested more than {} levels deep", MAX_INCLUDE_DEPTH);
+            let e = ParseError::new(include.line, include.column, reason);
+            return self.add_to_report(report, "<", source, 0, vec![e]);
         }
-        let mut line_iter = self.strings[1].lines();
-        let first_line = line_iter.next().unwrap();
-        let num_vertices = first_line.split_whitespace().next().unwrap().parse::<usize>().unwrap();
-        let lines: Vec<&str> = line_iter.collect();
-        'line: for line in &lines[num_vertices..] {
-            let mut line_iter = line.split_whitespace();
-            if line_iter.next().unwrap() != "3" {
-                continue 'line;
+        let path = Path::new(source.file).parent().unwrap_or(Path::new("")).join(&name);
+        let input = match fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(e) => {
+                let e = ParseError::new(
+                    include.line,
+                    include.column,
+                    format!("can not include '{}': {}", name, e),
+                );
+                return self.add_to_report(report, "<", source, 0, vec![e]);
             }
-            let positions: Vec<String> = line_iter
-                .map(|s| s.parse::<usize>().unwrap())
-                .map(|i| lines[i])
-                .map(|s| s.to_string())
-                .collect();
-            if positions.len() == 3 {
-                let triangle = Triangle::from_string([&positions[0], &positions[1], &positions[2]], self.default_color);
-                match triangle {
-                    Ok(t) => {
-                        self.objects.1.push(t);
-                    }
-                    _ => (),
-                }
+        };
+        let file = path.display().to_string();
+        let included = Source {
+            file: &file,
+            depth: source.depth + 1,
+        };
+        let mut tokens = TokenStream::new(&input);
+        while tokens.peek().is_some() {
+            self.read_item(&mut tokens, included, report)?;
+        }
+        Ok(())
+    }
+
+    //reads the objects of a section with the given header
+    fn read_section(
+        &mut self,
+        header: Token,
+        tokens: &mut TokenStream,
+        source: Source,
+        report: &mut ParseReport,
+    ) -> Result<(), ParseError> {
+        let (loaded, skipped) = match match_header(&header.text) {
+            Some(OffType::Off(format)) => {
+                let mut faces = Vec::new();
+                let counts = collect(read_off(tokens, format, self.default_color), &mut faces);
+                self.objects.triangles.extend(faces.into_iter().flatten());
+                counts
+            }
+            Some(OffType::Tri(n, colored)) => collect(
+                read_tris(tokens, n, colored, self.default_color),
+                &mut self.objects.triangular_patches,
+            ),
+            Some(OffType::BSpline(n, m, format)) if format.rational => {
+                let mut surfaces = Vec::new();
+                let counts = collect(
+                    read_rational_bsplines(tokens, n, m, format, self.default_color),
+                    &mut surfaces,
+                );
+                self.objects.rational_rectangles.extend(surfaces.into_iter().flatten());
+                counts
+            }
+            Some(OffType::BSpline(n, m, format)) => {
+                let mut surfaces = Vec::new();
+                let counts = collect(read_bsplines(tokens, n, m, format, self.default_color), &mut surfaces);
+                self.objects.rectangles.extend(surfaces.into_iter().flatten());
+                counts
+            }
+            Some(OffType::Mesh(format)) => {
+                let mut grids = Vec::new();
+                let counts = collect(read_mesh(tokens, format, self.default_color), &mut grids);
+                self.objects.triangles.extend(grids.into_iter().flatten());
+                counts
+            }
+            Some(OffType::Curve(n, colored)) => collect(
+                read_curves(tokens, n, colored, self.default_color),
+                &mut self.objects.curves,
+            ),
+            Some(OffType::Rect(n, m, format)) if format.rational => {
+                let patches = read_rational_rects(tokens, n, m, format, self.default_color);
+                collect(patches, &mut self.objects.rational_rectangles)
+            }
+            Some(OffType::Rect(n, m, format)) => collect(
+                read_rects(tokens, n, m, format, self.default_color),
+                &mut self.objects.rectangles,
+            ),
+            None => {
+                //an unknown keyword or values without a header, eg. before the first one, they are skipped up to the next keyword
+                let reason = match header.is_keyword() {
+                    true => format!("unknown keyword '{}'", header.text),
+                    false => format!("values without a header"),
+                };
+                tokens.skip_values();
+                (0, vec![ParseError::new(header.line, header.column, reason)])
+            }
+        };
+        let section = match header.is_keyword() {
+            true => header.text.clone(),
+            false => String::from("no header"),
+        };
+        self.add_to_report(report, &section, source, loaded, skipped)
+    }
+
+    //adds the counts of a section to the report, in strict mode the first error is returned instead
+    fn add_to_report(
+        &self,
+        report: &mut ParseReport,
+        section: &str,
+        source: Source,
+        loaded: usize,
+        skipped: Vec<ParseError>,
+    ) -> Result<(), ParseError> {
+        let skipped: Vec<ParseError> = skipped
+            .into_iter()
+            .map(|e| e.in_section(section).in_file(source.file))
+            .collect();
+        if self.strict {
+            if let Some(e) = skipped.first() {
+                return Err(e.clone());
             }
         }
+        report.add(section, loaded, skipped);
+        Ok(())
+    }
 
+    //transforms all objects that were added after the counts were taken
+    fn transform_objects(&mut self, counts: ObjectCounts, m: Mat4) {
+        if m == Mat4::IDENTITY {
+            return;
+        }
+        self.objects.transform_since(counts, m);
     }
 
     fn build_meshes(self) -> Vec<Mesh> {
         let mut meshes = Vec::<Mesh>::new();
-        //None
-        //do nothing
+        let num_patches = self.objects.num_patches();
 
         //Triangle
-        meshes.push(Triangle::triangle_list_to_mesh(self.objects.1));
-
-        //Rect11
-        let mut subdiv = SubdivisionSet::new();
-        subdiv.elements = self.objects.2;
-        subdiv.subdivide();
-        meshes.push(Triangle::triangle_list_to_mesh(subdiv.to_triangles()));
+        meshes.push(Triangle::triangle_list_to_mesh(self.objects.triangles));
 
-        //Rect22
-        let mut subdiv = SubdivisionSet::new();
-        subdiv.elements = self.objects.3;
-        subdiv.subdivide();
-        meshes.push(Triangle::triangle_list_to_mesh(subdiv.to_triangles()));
-        //Rect33
-        let mut subdiv = SubdivisionSet::new();
-        subdiv.elements = self.objects.4;
-        subdiv.subdivide();
-        meshes.push(Triangle::triangle_list_to_mesh(subdiv.to_triangles()));
+        //Rectangles of all degrees are stitched together, so that neighbouring patches do not have cracks
+        let limit = match self.depth {
+            Some(depth) => 4usize.pow(depth),
+            None => (ELEMENT_LIMIT + num_patches.max(1) - 1) / num_patches.max(1),
+        };
+        //with a tolerance all patches together are subdivided into at most about ADAPTIVE_ELEMENT_LIMIT pieces
+        let adaptive_limit = (ADAPTIVE_ELEMENT_LIMIT / num_patches.max(1)).max(1);
+        let mut stitch = StitchSet::new();
+        let rectangles = &self.objects.rectangles;
+        add_patches(&mut stitch, rectangles, limit, adaptive_limit, self.tolerance);
+        let rational_rectangles = &self.objects.rational_rectangles;
+        add_patches(&mut stitch, rational_rectangles, limit, adaptive_limit, self.tolerance);
+        let triangular_patches = &self.objects.triangular_patches;
+        add_triangular_patches(&mut stitch, triangular_patches, limit, adaptive_limit, self.tolerance);
+        let patch_triangles = stitch.to_triangles();
+        meshes.push(Triangle::triangle_list_to_mesh(patch_triangles));
 
-        //Rect44
-        let mut subdiv = SubdivisionSet::new();
-        subdiv.elements = self.objects.5;
-        subdiv.subdivide();
-        meshes.push(Triangle::triangle_list_to_mesh(subdiv.to_triangles()));
+        //curves are drawn as lines, the pieces of a curve need not be the same size since lines can not have cracks
+        if self.objects.curves.len() > 0 {
+            let adaptive_limit = (ADAPTIVE_ELEMENT_LIMIT / self.objects.curves.len()).max(1);
+            let mut segments = Vec::<Segment>::new();
+            for c in &self.objects.curves {
+                let mut subdiv = SubdivisionSet::new();
+                subdiv.elements = vec![c.clone()];
+                match self.tolerance {
+                    Some(t) => subdiv.subdivide_adaptive_until(t, adaptive_limit),
+                    None => subdiv.subdivide_until(self.depth.map_or(CURVE_SEGMENTS, |d| 2usize.pow(d))),
+                }
+                segments.extend(subdiv.to_segments());
+            }
+            meshes.push(Segment::segment_list_to_mesh(segments));
+        }
 
         meshes
     }
 
-    pub fn parse_file<P>(mut self, path: P) -> Result<Vec<Mesh>, String>
+    /*
+        reads all objects of the file and builds their meshes
+        objects that can not be read are skipped and listed in the report, in strict mode the first one is returned as error
+    */
+    pub fn parse_file<P>(mut self, path: P) -> Result<(Vec<Mesh>, ParseReport), ParseError>
     where
         P: AsRef<Path>,
     {
-        let file = match File::open(path) {
-            Ok(file) => file,
-            Err(e) => return Err(e.to_string()),
+        let name = path.as_ref().display().to_string();
+        let input = match fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(e) => return Err(ParseError::new(0, 0, e.to_string()).in_file(&name)),
         };
 
-        let mut current_type = OffType::None;
-        for line in io::BufReader::new(file).lines() {
-            let line = match line {
-                Ok(l) => l,
-                Err(e) => continue,
-            };
-            if line.is_empty() {
-                continue;
-            }
-            match match_line_type(&line) {
-                LineType::Values => {
-                    self.strings[offtype_id(&current_type)].push_str(&line);
-                    self.strings[offtype_id(&current_type)].push_str("\n");
-                }
-                LineType::Header(offtype) => {
-                    current_type = offtype;
-                }
-            }
+        let report = match is_obj_file(path.as_ref()) {
+            true => self.read_obj_file(&input, &name)?,
+            false => self.read_objects(&mut TokenStream::new(&input), &name)?,
+        };
+        let meshes = self.build_meshes();
+        Ok((meshes, report))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_headers() {
+        assert_eq!(rect_degree("BEZ323"), Some((3, 2, 3)));
+        assert_eq!(rect_degree("BEZ513"), Some((5, 1, 3)));
+        assert_eq!(rect_degree("BEZ993"), Some((9, 9, 3)));
+        assert_eq!(rect_degree("BEZ334"), Some((3, 3, 4)));
+        assert_eq!(rect_degree("BEZ335"), None);
+        assert_eq!(rect_degree("BEZ3333"), None);
+        assert_eq!(rect_degree("BEZ33"), None);
+        assert_eq!(rect_degree("CBEZ333"), None);
+        assert_eq!(rect_degree("0.5 0.5 0.5"), None);
+
+        //all combinations of the prefixes, degrees and dimensions
+        let format = |textured: bool, rational: bool, colored: bool| NetFormat {
+            textured: textured,
+            rational: rational,
+            colored: colored,
+        };
+        assert_eq!(
+            match_header("BEZ333"),
+            Some(OffType::Rect(3, 3, format(false, false, false)))
+        );
+        assert_eq!(
+            match_header("CBEZ313"),
+            Some(OffType::Rect(3, 1, format(false, false, true)))
+        );
+        assert_eq!(
+            match_header("STBEZ114"),
+            Some(OffType::Rect(1, 1, format(true, true, false)))
+        );
+        assert_eq!(
+            match_header("STCBEZ224"),
+            Some(OffType::Rect(2, 2, format(true, true, true)))
+        );
+        assert_eq!(match_header("CSTBEZ333"), None);
+        assert_eq!(match_header("CCBEZ333"), None);
+    }
+
+    #[test]
+    fn uncolored_patches() {
+        let input =
+            "BEZ113\n0 0 0\n1 0 0\n0 1 0\n1 1 0\nCBEZ113\n0 0 0 1 0 0 0 1 0 1 1 0\n1 0 0\n1 0 0\n1 0 0\n1 0 0\n\
+                     BEZ213 0 0 0 1 0 0 2 0 0 0 1 0 1 1 0 2 1 0";
+        let gray = Color::new(0.5, 0.5, 0.5);
+        let mut builder = MeshBuilder::new(gray, None, false);
+        let report = builder.read_objects(&mut TokenStream::new(input), "a.off").unwrap();
+        assert_eq!(report.num_skipped(), 0);
+        assert_eq!(builder.objects.rectangles.len(), 3);
+        assert_eq!(Surface::color(&builder.objects.rectangles[0], 0.5, 0.5), gray);
+        assert_eq!(Surface::color(&builder.objects.rectangles[1], 0.5, 0.5), Color::X);
+        assert_eq!(builder.objects.rectangles[2].degree(), (2, 1));
+        assert_eq!(Surface::color(&builder.objects.rectangles[2], 0.5, 0.5), gray);
+    }
+
+    #[test]
+    fn curve_headers() {
+        assert_eq!(curve_degree("BEZ33"), Some(3));
+        assert_eq!(curve_degree("BEZ93"), Some(9));
+        assert_eq!(curve_degree("BEZ03"), None);
+        assert_eq!(curve_degree("BEZ34"), None);
+        assert_eq!(curve_degree("BEZ333"), None);
+        assert_eq!(match_header("CBEZ13"), Some(OffType::Curve(1, true)));
+        assert_eq!(match_header("BEZ23"), Some(OffType::Curve(2, false)));
+        assert_eq!(match_header("CBEZ333"), Some(OffType::Rect(3, 3, CBEZ)));
+        assert_eq!(match_header("STCBEZ33"), None);
+    }
+
+    #[test]
+    fn primitive_names() {
+        assert_eq!(primitive_name("NCOFF"), Some(String::from("polygon")));
+        assert_eq!(
+            primitive_name("STCBEZ513"),
+            Some(String::from("bezier rectangle of degree 5x1"))
+        );
+        assert_eq!(
+            primitive_name("CBSP224"),
+            Some(String::from("rational B-spline surface of degree 2x2"))
+        );
+        assert_eq!(primitive_name("BEZ43"), Some(String::from("bezier curve of degree 4")));
+        assert_eq!(
+            primitive_name("CBBP"),
+            Some(String::from("bezier rectangle of degree 3x3"))
+        );
+        assert_eq!(
+            primitive_name("CTBEZ"),
+            Some(String::from("bezier triangle of degree 3"))
+        );
+        assert_eq!(
+            primitive_name("TBEZ53"),
+            Some(String::from("bezier triangle of degree 5"))
+        );
+        assert_eq!(primitive_name("INST"), None);
+        let mut report = ParseReport::default();
+        report.add("CBEZ333", 2, Vec::new());
+        report.add(
+            "<",
+            0,
+            vec![ParseError::new(1, 1, String::from("missing file name after '<'"))],
+        );
+        report.add("BEZ333", 1, Vec::new());
+        assert_eq!(
+            report.primitives(),
+            vec![(String::from("bezier rectangle of degree 3x3"), 3)]
+        );
+    }
+
+    #[test]
+    fn bspline_headers() {
+        assert_eq!(bspline_degree("BSP323"), Some((3, 2, 3)));
+        assert_eq!(bspline_degree("BSP104"), None);
+        let rational = NetFormat { rational: true, ..CBEZ };
+        assert_eq!(match_header("CBSP334"), Some(OffType::BSpline(3, 3, rational)));
+        assert_eq!(match_header("CBSP33"), None);
+
+        //a bilinear surface with an interior knot in each direction becomes four patches
+        let input = "CBSP113\n3 3\n0 0 1 2 2\n0 0 1 2 2\n0 0 0 1 0 0 2 0 0\n0 1 0 1 1 1 2 1 0\n0 2 0 1 2 0 2 2 0\n\
+                     1 0 0\n0 1 0\n0 0 1\n1 1 1\n";
+        let mut builder = MeshBuilder::new(Color::ONE, None, false);
+        let report = builder.read_objects(&mut TokenStream::new(input), "a.off").unwrap();
+        assert_eq!(report.to_string(), "CBSP113: 1 loaded, 0 skipped\n");
+        assert_eq!(builder.objects.rectangles.len(), 4);
+    }
+
+    #[test]
+    fn off_headers() {
+        assert_eq!(off_format("OFF"), Some(OffFormat::default()));
+        assert_eq!(off_format("COFF"), Some(COFF));
+        let cnoff = OffFormat {
+            colored: true,
+            normals: true,
+            textured: false,
+        };
+        assert_eq!(off_format("CNOFF"), Some(cnoff));
+        assert_eq!(off_format("NCOFF"), Some(cnoff));
+        assert_eq!(off_format("CCOFF"), None);
+        assert_eq!(off_format("XOFF"), None);
+        assert_eq!(off_format("OFF3"), None);
+
+        let textured = |format: OffFormat| {
+            OffType::Off(OffFormat {
+                textured: true,
+                ..format
+            })
+        };
+        assert_eq!(match_header("STOFF"), Some(textured(OffFormat::default())));
+        assert_eq!(match_header("STCNOFF"), Some(textured(cnoff)));
+        assert_eq!(match_header("CSTOFF"), None);
+        let textured = NetFormat { textured: true, ..CBEZ };
+        assert_eq!(match_header("STCBEZ333"), Some(OffType::Rect(3, 3, textured)));
+        let rational = NetFormat { rational: true, ..CBEZ };
+        assert_eq!(match_header("CBEZ224"), Some(OffType::Rect(2, 2, rational)));
+        assert_eq!(match_header("CBBP"), Some(OffType::Rect(3, 3, CBEZ)));
+        assert_eq!(match_header("STCBBP"), Some(OffType::Rect(3, 3, textured)));
+        assert_eq!(match_header("BBP"), match_header("BEZ333"));
+        assert_eq!(match_header("CTBEZ"), Some(OffType::Tri(3, true)));
+        assert_eq!(match_header("TBEZ"), Some(OffType::Tri(3, false)));
+        assert_eq!(match_header("TBEZ33"), match_header("TBEZ"));
+        assert_eq!(match_header("CTBEZ53"), Some(OffType::Tri(5, true)));
+        assert_eq!(match_header("TBEZ13"), Some(OffType::Tri(1, false)));
+        assert_eq!(match_header("TBEZ03"), None);
+        assert_eq!(match_header("TBEZ34"), None);
+        assert_eq!(match_header("TBEZ3"), None);
+        assert_eq!(match_header("STTBEZ"), None);
+        assert_eq!(match_header("STCTBEZ33"), None);
+
+        //triangular patches keep the degree of their header
+        let quadratic = "0 0 0\n1 0 0\n2 0 0\n0 1 0\n1 1 1\n0 2 0\n";
+        let input = format!(
+            "TBEZ23\n{}{}CTBEZ13\n0 0 0\n1 0 0\n0 1 0\n1 0 0\n0 1 0\n0 0 1\n",
+            quadratic, quadratic
+        );
+        let mut builder = MeshBuilder::new(Color::ONE, None, false);
+        let report = builder.read_objects(&mut TokenStream::new(&input), "a.off").unwrap();
+        assert_eq!(
+            report.to_string(),
+            "TBEZ23: 2 loaded, 0 skipped\nCTBEZ13: 1 loaded, 0 skipped\n"
+        );
+        let degrees: Vec<usize> = builder.objects.triangular_patches.iter().map(|p| p.degree()).collect();
+        assert_eq!(degrees, vec![2, 2, 1]);
+        assert_eq!(match_header("CBEZ513"), Some(OffType::Rect(5, 1, CBEZ)));
+    }
+
+    const COFF: OffFormat = OffFormat {
+        colored: true,
+        normals: false,
+        textured: false,
+    };
+
+    const CBEZ: NetFormat = NetFormat {
+        textured: false,
+        rational: false,
+        colored: true,
+    };
+
+    //the objects read by f, or the error of the first one that can not be read
+    fn all<T>(input: &str, f: impl Fn(&mut TokenStream) -> Vec<Result<T, ParseError>>) -> Result<Vec<T>, ParseError> {
+        f(&mut TokenStream::new(input)).into_iter().collect()
+    }
+
+    #[test]
+    fn off_errors() {
+        let off = |tokens: &mut TokenStream| read_off(tokens, OffFormat::default(), Color::ONE);
+        assert_eq!(all("3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2", off).unwrap().len(), 1);
+
+        let e = all("3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 3", off).unwrap_err();
+        assert_eq!((e.line, e.column), (5, 7));
+
+        let e = all("3 1 0\n0 0 0\n1 0.x 0\n0 1 0\n3 0 1 2", off).unwrap_err();
+        assert_eq!(
+            (e.line, e.column, e.reason.as_str()),
+            (3, 3, "invalid coordinate '0.x'")
+        );
+
+        let e = all("4 1 0\n0 0 0", off).unwrap_err();
+        assert_eq!(
+            (e.line, e.reason.as_str()),
+            (2, "missing vertex value, 3 values expected but 0 found")
+        );
+    }
+
+    #[test]
+    fn off_layout() {
+        let off = |tokens: &mut TokenStream| read_off(tokens, OffFormat::default(), Color::ONE);
+        //all vertices on one line, with comments, faces end with their line because of the optional face color
+        let triangles = all("3 2 0 0 0 0 1 0 0 0 1 0 # vertices\n3 0 1 2\n3 2 1 0 # faces", off).unwrap();
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[1][0].points()[0], Vec3::new(0., 1., 0.));
+
+        //vertex colors and face colors
+        let triangles = all("3 1 0\n0 0 0 1 0 0\n1 0 0 0 1 0\n0 1 0 0 0 1\n3 0 1 2 1 1 1", off).unwrap();
+        assert_eq!(triangles.len(), 1);
+        let coff = |tokens: &mut TokenStream| read_off(tokens, COFF, Color::ONE);
+        let triangles = all("3 1 0\n0 0 0\n1 0 0 1\n1 0 0\n0 1 0 1\n0 1 0\n0 0 1 1\n3 0 1 2", coff).unwrap();
+        assert_eq!(triangles.len(), 1);
+    }
+
+    #[test]
+    fn off_colors() {
+        let off = |tokens: &mut TokenStream| read_off(tokens, OffFormat::default(), Color::ONE);
+        let input = "3 3 0\n0 0 0 1 0 0\n1 0 0 0 1 0\n0 1 0 0 0 1\n3 0 1 2\n3 0 1 2 1 0 0 0.5\n3 0 1 2 0 0.5 0";
+        let faces = all(input, off).unwrap();
+        assert_eq!(faces[0][0].colors()[1], Color::new(0., 1., 0.));
+        assert_eq!(faces[0][0].alphas(), [1.; 3]);
+        assert_eq!(faces[1][0].colors(), [Color::new(1., 0., 0.); 3]);
+        assert_eq!(faces[1][0].alphas(), [0.5; 3]);
+        assert_eq!(faces[2][0].colors()[0], Color::new(0., 0.5, 0.));
+
+        //a single integer above 1 makes all colors of the section integers, even the ones of only 0 and 1
+        let input = "3 2 0\n0 0 0 1 0 0\n1 0 0 0 1 0\n0 1 0 0 0 1\n3 0 1 2\n3 0 1 2 255 0 0 128";
+        let faces = all(input, off).unwrap();
+        assert_eq!(faces[0][0].colors()[0], Color::new(1. / 255., 0., 0.));
+        assert_eq!(faces[1][0].colors(), [Color::new(1., 0., 0.); 3]);
+        assert_eq!(faces[1][0].alphas(), [128. / 255.; 3]);
+
+        let coff = |tokens: &mut TokenStream| read_off(tokens, COFF, Color::ONE);
+        let input = "3 1 0\n0 0 0 1 0 0 0.5\n1 0 0 0 1 0 0.5\n0 1 0 0 0 1 1\n3 0 1 2";
+        let faces = all(input, coff).unwrap();
+        assert_eq!(faces[0][0].alphas(), [0.5, 0.5, 1.]);
+
+        let e = all("3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2 1 0.x 0", off).unwrap_err();
+        assert_eq!((e.line, e.column), (5, 11));
+    }
+
+    #[test]
+    fn off_normals() {
+        let noff = OffFormat {
+            colored: false,
+            normals: true,
+            textured: false,
+        };
+        let read = |tokens: &mut TokenStream| read_off(tokens, noff, Color::ONE);
+        let input = "4 2 0\n0 0 0 0 0 2\n1 0 0 0 1 1\n0 1 0 0 0 1\n1 1 0 1 0 0\n3 0 1 2\n3 1 3 2 1 0 0";
+        let faces = all(input, read).unwrap();
+        let [n0, n1, n2] = faces[0][0].normals();
+        assert_eq!((n0, n2), (Vec3::Z, Vec3::Z));
+        assert!((n1 - Vec3::new(0., 1., 1.).normalize()).length() < 1e-6);
+        assert_eq!(faces[1][0].normals()[1], Vec3::X);
+        assert_eq!(faces[1][0].colors(), [Color::new(1., 0., 0.); 3]);
+        let e = all("3 1 0\n0 0 0 0 0 1\n1 0 0 0 0 1\n0 1 0 0 0.x 1\n3 0 1 2", read).unwrap_err();
+        assert_eq!(
+            (e.line, e.column, e.reason.as_str()),
+            (4, 9, "invalid normal component '0.x'")
+        );
+
+        //normals come before the colors
+        let cnoff = OffFormat {
+            colored: true,
+            normals: true,
+            textured: false,
+        };
+        let read = |tokens: &mut TokenStream| read_off(tokens, cnoff, Color::ONE);
+        let input = "3 1 0\n0 0 0 0 0 1 1 0 0 1\n1 0 0 0 0 1 0 1 0 1\n0 1 0 0 0 1 0 0 1 0.5\n3 0 1 2";
+        let faces = all(input, read).unwrap();
+        assert_eq!(faces[0][0].normals(), [Vec3::Z; 3]);
+        assert_eq!(faces[0][0].colors()[2], Color::new(0., 0., 1.));
+        assert_eq!(faces[0][0].alphas(), [1., 1., 0.5]);
+    }
+
+    #[test]
+    fn mesh_headers() {
+        assert_eq!(mesh_format("MESH"), Some(MeshFormat::default()));
+        let cnmesh = MeshFormat {
+            colored: true,
+            normals: true,
+            ..MeshFormat::default()
+        };
+        assert_eq!(mesh_format("CNMESH"), Some(cnmesh));
+        let wrapped = MeshFormat {
+            textured: true,
+            wrap_u: true,
+            wrap_v: true,
+            ..MeshFormat::default()
+        };
+        assert_eq!(match_header("UuvMESH"), Some(OffType::Mesh(wrapped)));
+        assert_eq!(mesh_format("uuMESH"), None);
+        let textured = MeshFormat {
+            textured: true,
+            ..MeshFormat::default()
+        };
+        assert_eq!(mesh_format("UVMESH"), Some(textured));
+        assert_eq!(
+            mesh_format("CUVuMESH"),
+            Some(MeshFormat {
+                colored: true,
+                wrap_u: true,
+                ..textured
+            })
+        );
+        assert_eq!(mesh_format("UVUMESH"), None);
+        assert_eq!(mesh_format("VMESH"), None);
+        assert_eq!(match_header("STMESH"), None);
+    }
+
+    #[test]
+    fn meshes() {
+        let grid = |format: MeshFormat, input: &str| all(input, |tokens| read_mesh(tokens, format, Color::ONE));
+        let input = "3 2\n0 0 0 1 0 0 2 0 0\n0 1 0 1 1 0 2 1 1\n";
+        let triangles = &grid(MeshFormat::default(), input).unwrap()[0];
+        assert_eq!(triangles.len(), 4);
+        assert_eq!(triangles[0].colors(), [Color::ONE; 3]);
+
+        //a height field, wrapping it in u closes it between the last and the first column
+        let heights = MeshFormat {
+            heights: true,
+            ..MeshFormat::default()
+        };
+        let triangles = &grid(heights, "2 2 0 1 2 3 2 2 1 1 1 1").unwrap();
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[0].len(), 2);
+        let points: Vec<Vec3> = triangles[0].iter().flat_map(|t| t.points()).collect();
+        assert!(points.contains(&Vec3::new(1., 1., 3.)));
+        let wrapped = MeshFormat {
+            wrap_u: true,
+            ..heights
+        };
+        assert_eq!(grid(wrapped, "3 2 0 0 0 0 0 0").unwrap()[0].len(), 6);
+        //a single column is not closed with itself
+        let triangles = &grid(wrapped, "1 3 0 0 0").unwrap()[0];
+        assert!(triangles.is_empty());
+        let both = MeshFormat {
+            wrap_v: true,
+            ..wrapped
+        };
+        let triangles = &grid(both, "1 3 0 1 2").unwrap()[0];
+        assert!(triangles.is_empty());
+
+        //colors are rgba and come after the normals
+        let cnmesh = MeshFormat {
+            colored: true,
+            normals: true,
+            ..heights
+        };
+        let input = "2 2\n0 0 0 1 1 0 0 1\n0 0 0 1 1 0 0 1\n0 0 0 1 1 0 0 1\n0 0 0 1 0 0 1 0.5\n";
+        let triangles = &grid(cnmesh, input).unwrap()[0];
+        assert_eq!(triangles[0].normals(), [Vec3::Z; 3]);
+        assert!(triangles.iter().any(|t| t.alphas().contains(&0.5)));
+
+        let e = grid(MeshFormat::default(), "2 2 0 0 0 1 0 0 0 1 0 1 1 0.x").unwrap_err();
+        assert_eq!(
+            (e.line, e.column, e.reason.as_str()),
+            (1, 27, "invalid coordinate '0.x'")
+        );
+        let e = grid(MeshFormat::default(), "2 2 0 0 0").unwrap_err();
+        assert_eq!(e.reason, "missing vertex value, 12 values expected but 3 found");
+        let e = grid(MeshFormat::default(), "4294967296 4294967296 0 0 0").unwrap_err();
+        assert_eq!((e.line, e.column), (1, 1));
+        assert_eq!(e.reason, "grid size too large: 4294967296 x 4294967296");
+    }
+
+    #[test]
+    fn obj_files() {
+        let input = "# a square and a triangle\no square\nv 0 0 0\nv 1 0 0 1 0 0\nv 1 1 0 0 1 0\nv 0 1 0 0 0 1\n\
+                     vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 2\nusemtl red\nf 1/1/1 2/2/1 3/3/1 4/1/1\n\
+                     v 0 0 4 2\nf -4 -3 -2\nl 1 2\n";
+        let faces = read_obj(input, Color::ONE);
+        assert_eq!(faces.len(), 2);
+        let square = faces[0].as_ref().unwrap();
+        assert_eq!(square.len(), 2);
+        assert_eq!(square[0].normals(), [Vec3::Z; 3]);
+        assert_eq!(square[0].uvs()[1], Vec2::new(1., 0.));
+        //not all vertices of the square have a color
+        assert_eq!(square[0].colors(), [Color::ONE; 3]);
+        let triangle = &faces[1].as_ref().unwrap()[0];
+        assert_eq!(triangle.points(), [Vec3::X, Vec3::new(1., 1., 0.), Vec3::Y]);
+        assert_eq!(triangle.colors(), [Color::X, Color::Y, Color::Z]);
+        let weighted = tokens::tokenize("v 0 0 4 2");
+        assert_eq!(
+            read_obj_vertex(&weighted[0], &weighted[1..]).unwrap().position,
+            Vec3::new(0., 0., 4.)
+        );
+
+        let error = |input: &str| read_obj(input, Color::ONE).remove(0).unwrap_err().reason;
+        assert_eq!(
+            error("v 0 0 0\nv 1 0 0\nf 1 2 3\n"),
+            "vertex index 3 out of range, 2 given so far"
+        );
+        assert_eq!(
+            error("v 0 0 0\nf 1 1 -2\n"),
+            "vertex index -2 out of range, 1 given so far"
+        );
+        assert_eq!(
+            error("v 0 0 0\nf 1 1 0\n"),
+            "vertex index 0 out of range, 1 given so far"
+        );
+        assert_eq!(error("v 0 0 0\nf 1 1/a 1\n"), "invalid texture coordinate index '1/a'");
+        assert_eq!(error("v 0 0 0\nf 1 1\n"), "a face needs at least 3 vertices, 2 given");
+        assert_eq!(error("v 0 0 0.x\nf 1 1 1\n"), "invalid coordinate '0.x'");
+        assert_eq!(
+            error("v 0 0 0 2 0 0\nf 1 1 1\n"),
+            "color component 2 is not between 0 and 1"
+        );
+        assert_eq!(
+            error("v 0 0\nf 1 1 1\n"),
+            "a vertex needs 3 coordinates and optionally a weight or a color, 2 values given"
+        );
+        assert_eq!(
+            error("vn 0 1\nv 0 0 0\nf 1//1 1//1 1//1\n"),
+            "missing normal component, 3 values expected but 2 found"
+        );
+        assert_eq!(error("1 2 3\n"), "values without a keyword");
+        assert_eq!(primitive_name(OBJ_SECTION), Some(String::from("polygon")));
+        assert!(is_obj_file(Path::new("a/b.OBJ")) && !is_obj_file(Path::new("obj.off")));
+    }
+
+    #[test]
+    fn off_texture_coordinates() {
+        let stoff = OffFormat {
+            textured: true,
+            ..OffFormat::default()
+        };
+        let read = |tokens: &mut TokenStream| read_off(tokens, stoff, Color::ONE);
+        let faces = all("3 1 0\n0 0 0 0 0\n1 0 0 1 0\n0 1 0 0 1\n3 0 1 2", read).unwrap();
+        assert_eq!(faces[0][0].uvs(), [Vec2::ZERO, Vec2::X, Vec2::Y]);
+        assert_eq!(faces[0][0].colors(), [Color::ONE; 3]);
+
+        //texture coordinates come after the colors
+        let stcoff = OffFormat { colored: true, ..stoff };
+        let read = |tokens: &mut TokenStream| read_off(tokens, stcoff, Color::ONE);
+        let input = "3 1 0\n0 0 0 1 0 0 1 0 0\n1 0 0 0 1 0 1 1 0\n0 1 0 0 0 1 1 0.5 1\n3 0 1 2";
+        let faces = all(input, read).unwrap();
+        assert_eq!(faces[0][0].colors()[1], Color::new(0., 1., 0.));
+        assert_eq!(faces[0][0].uvs()[2], Vec2::new(0.5, 1.));
+
+        //without texture coordinates all of them are 0
+        let off = |tokens: &mut TokenStream| read_off(tokens, OffFormat::default(), Color::ONE);
+        let faces = all("3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2", off).unwrap();
+        assert_eq!(faces[0][0].uvs(), [Vec2::ZERO; 3]);
+    }
+
+    #[test]
+    fn off_polygons() {
+        let off = |tokens: &mut TokenStream| read_off(tokens, OffFormat::default(), Color::ONE);
+        let faces = all(
+            "5 2 0\n0 0 0\n1 0 0\n1 1 0\n0 1 0\n0.5 0.5 1\n4 0 1 2 3\n5 0 1 4 2 3",
+            off,
+        )
+        .unwrap();
+        assert_eq!(faces[0].len(), 2);
+        assert_eq!(faces[1].len(), 3);
+        //a concave quad that can not be split into a fan around its first vertex
+        let faces = all("4 1 0\n0 0 0\n2 0 0\n0.5 0.5 0\n0 2 0\n4 1 2 3 0", off).unwrap();
+        let mut area = 0.;
+        for t in &faces[0] {
+            let [a, b, c] = t.points();
+            let doubled_area = (b - a).cross(c - a).z;
+            assert!(doubled_area > 0.);
+            area += doubled_area * 0.5;
         }
+        assert_eq!(area, 1.);
+    }
 
-        self.build_objects();
-        let meshes = self.build_meshes();
-        Ok(meshes)
+    #[test]
+    fn rect_errors() {
+        let patch = "0 0 0\n1 0 0\n0 1 0\n1 1 0\n1 0 0\n0 1 0\n0 0 1\n1 1 1\n";
+        let rects = |tokens: &mut TokenStream| read_rects(tokens, 1, 1, CBEZ, Color::ONE);
+        assert_eq!(all(&patch.repeat(2), rects).unwrap().len(), 2);
+
+        //all values on few lines, colors with alpha
+        let input = "0 0 0 1 0 0 0 1 0 1 1 0\n1 0 0 1\n0 1 0 1\n0 0 1 1\n1 1 1 1 # colors\n".repeat(2);
+        assert_eq!(all(&input, rects).unwrap().len(), 2);
+
+        let broken = patch.repeat(2).replacen("0 0 1", "0 0 1e", 2);
+        let e = all(&broken, rects).unwrap_err();
+        assert_eq!(
+            (e.line, e.column, e.reason.as_str()),
+            (7, 5, "invalid color component '1e'")
+        );
+
+        let e = all(&patch[..patch.len() - 6], rects).unwrap_err();
+        assert_eq!(e.line, 7);
+    }
+
+    #[test]
+    fn skipped_objects() {
+        let input = "# a comment\nOFF\n3 3 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n3 0 1 5\n2 0 1\nSPHERE 1 2\nCBEZ113";
+        let mut builder = MeshBuilder::new(Color::ONE, None, false);
+        let report = builder.read_objects(&mut TokenStream::new(input), "a.off").unwrap();
+        assert_eq!(builder.objects.triangles.len(), 1);
+        assert_eq!(report.num_skipped(), 3);
+        let summary = report.to_string();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines[0], "OFF: 1 loaded, 2 skipped");
+        assert_eq!(
+            lines[1],
+            "    a.off:8:7 in section OFF: vertex index 5 out of range, 3 vertices declared"
+        );
+        assert_eq!(lines[3], "SPHERE: 0 loaded, 1 skipped");
+        assert_eq!(lines[5], "CBEZ113: 0 loaded, 0 skipped");
+
+        let mut builder = MeshBuilder::new(Color::ONE, None, true);
+        let e = builder.read_objects(&mut TokenStream::new(input), "a.off").unwrap_err();
+        assert_eq!((e.line, e.section.as_str()), (8, "OFF"));
     }
-}
\ No newline at end of file
+
+    const TRIANGLE: &str = "OFF 3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+
+    #[test]
+    fn instances() {
+        let translate = "1 0 0 0 0 1 0 0 0 0 1 0 5 0 0 1";
+        let scale = "{ 2 0 0 0 0 2 0 0 0 0 2 0 0 0 0 1 }";
+        //the inner instance is scaled first and then translated, the transform may come after the geom
+        let input = format!(
+            "{{ LIST\n{{ INST transform {} geom {{ INST geom {{ {} }} transform {} }} }}\n{{{}}} }}",
+            translate, TRIANGLE, scale, TRIANGLE
+        );
+        let mut builder = MeshBuilder::new(Color::ONE, None, false);
+        let report = builder.read_objects(&mut TokenStream::new(&input), "a.off").unwrap();
+        assert_eq!(report.num_skipped(), 0);
+        assert_eq!(builder.objects.triangles.len(), 2);
+        assert_eq!(builder.objects.triangles[0].points()[1], Vec3::new(7., 0., 0.));
+        assert_eq!(builder.objects.triangles[1].points()[1], Vec3::X);
+
+        let input = format!(
+            "{{ INST transform 1 2 3 geom {{ {} }} }}\n}}\n{{ LIST {}",
+            TRIANGLE, TRIANGLE
+        );
+        let mut builder = MeshBuilder::new(Color::ONE, None, false);
+        let report = builder.read_objects(&mut TokenStream::new(&input), "a.off").unwrap();
+        assert_eq!(builder.objects.triangles.len(), 2);
+        let reasons: Vec<&str> = report
+            .sections
+            .iter()
+            .flat_map(|s| &s.skipped)
+            .map(|e| e.reason.as_str())
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "missing matrix entry, 16 values expected but 3 found",
+                "'}' without matching '{'",
+                "'{' without matching '}'"
+            ]
+        );
+    }
+
+    #[test]
+    fn includes() {
+        let dir = std::env::temp_dir().join(format!("rover-includes-{}", std::process::id()));
+        fs::create_dir_all(dir.join("parts")).unwrap();
+        fs::write(
+            dir.join("scene.off"),
+            "{ LIST < parts/a.off\n{ INST transform 1 0 0 0 0 1 0 0 0 0 1 0 0 0 1 1 geom <parts/a.off } }",
+        )
+        .unwrap();
+        //includes are relative to the including file
+        fs::write(dir.join("parts/a.off"), format!("{}< b.off", TRIANGLE)).unwrap();
+        fs::write(
+            dir.join("parts/b.off"),
+            "CBEZ113 0 0 0 1 0 0 0 1 0 1 1 0\n1 1 1\n1 1 1\n1 1 1\n1 1 1\nOFF 0.x",
+        )
+        .unwrap();
+        fs::write(dir.join("loop.off"), "< loop.off").unwrap();
+
+        let builder = MeshBuilder::new(Color::ONE, None, false);
+        let (meshes, report) = builder.parse_file(dir.join("scene.off")).unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(report.to_string().lines().next(), Some("OFF: 2 loaded, 2 skipped"));
+        let e = &report.sections[0].skipped[0];
+        assert!(e.file.ends_with("b.off"));
+        assert_eq!(report.sections[1].loaded, 2);
+
+        let mut builder = MeshBuilder::new(Color::ONE, None, false);
+        let input = "< loop.off < missing.off";
+        let report = builder
+            .read_objects(&mut TokenStream::new(input), &dir.join("x.off").display().to_string())
+            .unwrap();
+        assert_eq!(report.sections[0].skipped.len(), 2);
+        assert!(report.sections[0].skipped[0]
+            .reason
+            .starts_with("includes are nested more than"));
+        assert!(report.sections[0].skipped[1]
+            .reason
+            .starts_with("can not include 'missing.off'"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn curves() {
+        let input = "CBEZ13\n0 0 0 1 0 0\n1 0 0\n0 0 1\nCBEZ23\n0 0 0 1 1 0 2 0 0\n1 1 1\n1 1 1\n";
+        let mut builder = MeshBuilder::new(Color::ONE, Some(0.01), false);
+        let report = builder.read_objects(&mut TokenStream::new(input), "a.off").unwrap();
+        assert_eq!(report.num_skipped(), 0);
+        assert_eq!(builder.objects.curves.len(), 2);
+        //the curves keep their degree instead of being elevated to the largest one
+        assert_eq!(builder.objects.curves[0].degree(), 1);
+        assert_eq!(builder.objects.curves[1].degree(), 2);
+        let meshes = builder.build_meshes();
+        assert_eq!(meshes.len(), 3);
+        assert!(crate::segment::is_line_mesh(&meshes[2]));
+    }
+}
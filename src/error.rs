@@ -0,0 +1,80 @@
+use std::fmt;
+
+/*
+    An error found while reading an input file
+    line and column start at 1, a value of 0 means the position is unknown
+    Parsers of single objects only see some lines of a section, they report the line relative to their input,
+    the builder then moves the error to its position in the file with locate, in_section and in_file
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub section: String,
+    pub reason: String,
+}
+
+impl ParseError {
+    pub fn new(line: usize, column: usize, reason: String) -> Self {
+        ParseError {
+            file: String::new(),
+            line: line,
+            column: column,
+            section: String::new(),
+            reason: reason,
+        }
+    }
+
+    //maps a line relative to the input of a parser to the line number of that input line, line_numbers[0] is the first line
+    pub fn locate(mut self, line_numbers: &[usize]) -> Self {
+        if self.line > 0 && self.line <= line_numbers.len() {
+            self.line = line_numbers[self.line - 1];
+        }
+        self
+    }
+
+    pub fn in_section(mut self, header: &str) -> Self {
+        self.section = header.to_string();
+        self
+    }
+
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.file = file.to_string();
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if self.line > 0 {
+            write!(f, ":{}", self.line)?;
+            if self.column > 0 {
+                write!(f, ":{}", self.column)?;
+            }
+        }
+        if !self.section.is_empty() {
+            write!(f, " in section {}", self.section)?;
+        }
+        write!(f, ": {}", self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let e = ParseError::new(2, 5, "invalid coordinate '1.o'".to_string());
+        assert_eq!(e.to_string(), ":2:5: invalid coordinate '1.o'");
+        let e = e.locate(&[10, 12, 13]).in_section("CBEZ113").in_file("a.off");
+        assert_eq!(e.line, 12);
+        assert_eq!(e.to_string(), "a.off:12:5 in section CBEZ113: invalid coordinate '1.o'");
+        let e = ParseError::new(0, 0, "No such file or directory".to_string()).in_file("b.off");
+        assert_eq!(e.to_string(), "b.off: No such file or directory");
+    }
+}
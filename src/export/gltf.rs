@@ -0,0 +1,310 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use super::{Attributes, IndexedMesh};
+
+pub const ATTRIBUTES: Attributes = Attributes {
+    normals: true,
+    uvs: true,
+};
+
+//constants of the gltf specification
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const LINES: u32 = 1;
+const TRIANGLES: u32 = 4;
+
+const GLB_MAGIC: u32 = 0x46546C67;
+const GLB_JSON_CHUNK: u32 = 0x4E4F534A;
+const GLB_BIN_CHUNK: u32 = 0x004E4942;
+
+/*
+    a gltf document with one buffer that holds the data of all meshes
+    each attribute and index list gets its own buffer view and accessor, whose json is collected while the meshes are added
+*/
+#[derive(Debug, Default)]
+struct Document {
+    buffer: Vec<u8>,
+    buffer_views: Vec<String>,
+    accessors: Vec<String>,
+    meshes: Vec<String>,
+    materials: Vec<String>,
+}
+
+impl Document {
+    fn new(meshes: &[IndexedMesh]) -> Self {
+        let mut document = Document::default();
+        for mesh in meshes.iter().filter(|m| !m.triangles.is_empty() || !m.lines.is_empty()) {
+            document.add_mesh(mesh);
+        }
+        document
+    }
+
+    //appends the bytes to the buffer as a buffer view with one accessor, returns the index of the accessor
+    fn add_accessor(&mut self, bytes: Vec<u8>, target: u32, accessor: String) -> usize {
+        let view = format!(
+            "{{ \"buffer\": 0, \"byteOffset\": {}, \"byteLength\": {}, \"target\": {} }}",
+            self.buffer.len(),
+            bytes.len(),
+            target
+        );
+        self.buffer.extend(bytes);
+        self.buffer_views.push(view);
+        let accessor = format!("{{ \"bufferView\": {}, {} }}", self.buffer_views.len() - 1, accessor);
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    //kind is the gltf type of the values, eg. VEC3 for arrays of 3 floats, bounds adds their minimum and maximum
+    fn add_floats<const N: usize>(&mut self, values: &[[f32; N]], kind: &str, bounds: bool) -> usize {
+        let bytes = values.iter().flatten().flat_map(|x| x.to_le_bytes()).collect();
+        let mut accessor = format!(
+            "\"componentType\": {}, \"count\": {}, \"type\": \"{}\"",
+            FLOAT,
+            values.len(),
+            kind
+        );
+        if bounds {
+            let mut min = [f32::INFINITY; N];
+            let mut max = [f32::NEG_INFINITY; N];
+            for v in values {
+                for i in 0..N {
+                    min[i] = min[i].min(v[i]);
+                    max[i] = max[i].max(v[i]);
+                }
+            }
+            let join = |x: [f32; N]| x.map(|x| x.to_string()).join(", ");
+            write!(accessor, ", \"min\": [{}], \"max\": [{}]", join(min), join(max)).unwrap();
+        }
+        self.add_accessor(bytes, ARRAY_BUFFER, accessor)
+    }
+
+    fn add_indices(&mut self, indices: &[usize]) -> usize {
+        let bytes = indices.iter().flat_map(|i| (*i as u32).to_le_bytes()).collect();
+        let accessor = format!(
+            "\"componentType\": {}, \"count\": {}, \"type\": \"SCALAR\"",
+            UNSIGNED_INT,
+            indices.len()
+        );
+        self.add_accessor(bytes, ELEMENT_ARRAY_BUFFER, accessor)
+    }
+
+    /*
+        adds a mesh with a primitive for its triangles and one for its lines, both use the same vertices
+        gltf needs the bounds of the positions and normals of unit length, so normals are only given for triangles,
+        lines have none
+    */
+    fn add_mesh(&mut self, mesh: &IndexedMesh) {
+        let positions: Vec<[f32; 3]> = mesh.vertices.iter().map(|v| v.position).collect();
+        let position = self.add_floats(&positions, "VEC3", true);
+        let colors: Vec<[f32; 4]> = mesh.vertices.iter().map(|v| v.color).collect();
+        let color = self.add_floats(&colors, "VEC4", false);
+        let mut attributes = format!("\"POSITION\": {}, \"COLOR_0\": {}", position, color);
+        if !mesh.triangles.is_empty() {
+            let normals: Vec<[f32; 3]> = mesh.vertices.iter().map(|v| v.normal).collect();
+            let uvs: Vec<[f32; 2]> = mesh.vertices.iter().map(|v| v.uv).collect();
+            let normal = self.add_floats(&normals, "VEC3", false);
+            let uv = self.add_floats(&uvs, "VEC2", false);
+            write!(attributes, ", \"NORMAL\": {}, \"TEXCOORD_0\": {}", normal, uv).unwrap();
+        }
+
+        //the vertex colors are multiplied with the base color, which is white by default
+        let transparent = colors.iter().any(|c| c[3] < 1.);
+        let alpha_mode = if transparent { "BLEND" } else { "OPAQUE" };
+        self.materials.push(format!(
+            "{{ \"pbrMetallicRoughness\": {{ \"metallicFactor\": 0, \"roughnessFactor\": 1 }}, \
+             \"alphaMode\": \"{}\", \"doubleSided\": true }}",
+            alpha_mode
+        ));
+        let material = self.materials.len() - 1;
+
+        let mut primitives = Vec::new();
+        if !mesh.triangles.is_empty() {
+            let indices: Vec<usize> = mesh.triangles.iter().flatten().copied().collect();
+            let indices = self.add_indices(&indices);
+            primitives.push((indices, TRIANGLES));
+        }
+        if !mesh.lines.is_empty() {
+            let indices: Vec<usize> = mesh.lines.iter().flatten().copied().collect();
+            let indices = self.add_indices(&indices);
+            primitives.push((indices, LINES));
+        }
+        let primitives: Vec<String> = primitives
+            .into_iter()
+            .map(|(indices, mode)| {
+                format!(
+                    "{{ \"attributes\": {{ {} }}, \"indices\": {}, \"material\": {}, \"mode\": {} }}",
+                    attributes, indices, material, mode
+                )
+            })
+            .collect();
+        self.meshes
+            .push(format!("{{ \"primitives\": [{}] }}", primitives.join(", ")));
+    }
+
+    /*
+        the json of the document, uri is the file of the buffer, which is left out in glb files
+        gltf does not allow empty lists, so a document without meshes only consists of its asset description
+    */
+    fn to_json(&self, uri: Option<&str>) -> String {
+        let mut json = String::from("{\n  \"asset\": { \"version\": \"2.0\", \"generator\": \"rover\" }");
+        if !self.meshes.is_empty() {
+            let nodes: Vec<String> = (0..self.meshes.len())
+                .map(|i| format!("{{ \"mesh\": {} }}", i))
+                .collect();
+            let scene_nodes: Vec<String> = (0..self.meshes.len()).map(|i| i.to_string()).collect();
+            let buffer = match uri {
+                Some(uri) => format!(
+                    "{{ \"byteLength\": {}, \"uri\": \"{}\" }}",
+                    self.buffer.len(),
+                    encode_uri(uri)
+                ),
+                None => format!("{{ \"byteLength\": {} }}", self.buffer.len()),
+            };
+            json.push_str(",\n  \"scene\": 0");
+            write!(json, ",\n  \"scenes\": [{{ \"nodes\": [{}] }}]", scene_nodes.join(", ")).unwrap();
+            let lists = [
+                ("nodes", &nodes),
+                ("meshes", &self.meshes),
+                ("materials", &self.materials),
+                ("accessors", &self.accessors),
+                ("bufferViews", &self.buffer_views),
+                ("buffers", &vec![buffer]),
+            ];
+            for (name, items) in lists {
+                write!(json, ",\n  \"{}\": [\n    {}\n  ]", name, items.join(",\n    ")).unwrap();
+            }
+        }
+        json.push_str("\n}\n");
+        json
+    }
+}
+
+//percent-encodes all characters of a file name that may not appear in a uri
+fn encode_uri(name: &str) -> String {
+    let mut uri = String::new();
+    for b in name.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => uri.push(b as char),
+            b => write!(uri, "%{:02X}", b).unwrap(),
+        }
+    }
+    uri
+}
+
+/*
+    writes the meshes as a gltf file, whose buffer is written to the bin writer and referenced by the name bin_file
+    nothing is written to the bin writer if there are no meshes
+*/
+pub fn write_gltf<W: Write, B: Write>(
+    writer: &mut W,
+    bin: &mut B,
+    bin_file: &str,
+    meshes: &[IndexedMesh],
+) -> io::Result<()> {
+    let document = Document::new(meshes);
+    writer.write_all(document.to_json(Some(bin_file)).as_bytes())?;
+    bin.write_all(&document.buffer)?;
+    writer.flush()?;
+    bin.flush()
+}
+
+//writes the meshes as a binary glb file, which holds the json and the buffer in one file
+pub fn write_glb<W: Write>(writer: &mut W, meshes: &[IndexedMesh]) -> io::Result<()> {
+    let document = Document::new(meshes);
+    //chunks are aligned to 4 bytes, json is padded with spaces and the buffer with zeros
+    let mut json = document.to_json(None).into_bytes();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    let mut buffer = document.buffer;
+    buffer.resize(buffer.len().div_ceil(4) * 4, 0);
+    let bin_chunk_length = if buffer.is_empty() { 0 } else { 8 + buffer.len() };
+    let length = 12 + 8 + json.len() + bin_chunk_length;
+
+    writer.write_all(&GLB_MAGIC.to_le_bytes())?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(length as u32).to_le_bytes())?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(&GLB_JSON_CHUNK.to_le_bytes())?;
+    writer.write_all(&json)?;
+    if !buffer.is_empty() {
+        writer.write_all(&(buffer.len() as u32).to_le_bytes())?;
+        writer.write_all(&GLB_BIN_CHUNK.to_le_bytes())?;
+        writer.write_all(&buffer)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::Segment;
+    use crate::triangle::Triangle;
+    use bevy::prelude::Vec3;
+
+    fn meshes() -> Vec<IndexedMesh> {
+        let triangles = vec![
+            Triangle::new([Vec3::ZERO, Vec3::X, Vec3::Y], [Vec3::ONE; 3]),
+            Triangle::new([Vec3::X, Vec3::new(1., 1., -2.), Vec3::Y], [Vec3::ONE; 3]).with_alphas([1., 0.5, 1.]),
+        ];
+        let segments = vec![Segment::new([Vec3::ZERO, Vec3::Z], [Vec3::X, Vec3::Y])];
+        vec![
+            IndexedMesh::from_meshes(&[Triangle::triangle_list_to_mesh(triangles)], ATTRIBUTES),
+            IndexedMesh::default(),
+            IndexedMesh::from_meshes(&[Segment::segment_list_to_mesh(segments)], ATTRIBUTES),
+        ]
+    }
+
+    #[test]
+    fn gltf_document() {
+        let mut json = Vec::new();
+        let mut bin = Vec::new();
+        write_gltf(&mut json, &mut bin, "a b.bin", &meshes()).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        //the triangles do not share vertices since their normals differ, the line has 2 vertices without normals
+        let vertices = 6;
+        assert_eq!(
+            bin.len(),
+            vertices * (3 + 4 + 3 + 2) * 4 + 6 * 4 + 2 * (3 + 4) * 4 + 2 * 4
+        );
+        assert!(json.contains(&format!(
+            "\"buffers\": [\n    {{ \"byteLength\": {}, \"uri\": \"a%20b.bin\" }}\n  ]",
+            bin.len()
+        )));
+        //the empty mesh is left out
+        assert!(json.contains("\"scenes\": [{ \"nodes\": [0, 1] }],"));
+        assert!(json.contains("\"count\": 6, \"type\": \"VEC3\", \"min\": [0, 0, -2], \"max\": [1, 1, 0] }"));
+        assert!(json.contains(
+            "{ \"attributes\": { \"POSITION\": 0, \"COLOR_0\": 1, \"NORMAL\": 2, \"TEXCOORD_0\": 3 }, \
+             \"indices\": 4, \"material\": 0, \"mode\": 4 }"
+        ));
+        assert!(json.contains(
+            "{ \"attributes\": { \"POSITION\": 5, \"COLOR_0\": 6 }, \"indices\": 7, \"material\": 1, \"mode\": 1 }"
+        ));
+        assert!(json.contains("\"alphaMode\": \"BLEND\""));
+        assert_eq!(encode_uri("ä.bin"), "%C3%A4.bin");
+    }
+
+    #[test]
+    fn glb_file() {
+        let mut glb = Vec::new();
+        write_glb(&mut glb, &meshes()).unwrap();
+        let word = |i: usize| u32::from_le_bytes(glb[i..i + 4].try_into().unwrap()) as usize;
+        assert_eq!((word(0), word(4), word(8)), (GLB_MAGIC as usize, 2, glb.len()));
+        let json_length = word(12);
+        assert_eq!((json_length % 4, word(16)), (0, GLB_JSON_CHUNK as usize));
+        let json = std::str::from_utf8(&glb[20..20 + json_length]).unwrap();
+        assert!(!json.contains("uri"));
+        let bin = 20 + json_length;
+        assert_eq!(word(bin + 4), GLB_BIN_CHUNK as usize);
+        assert_eq!(bin + 8 + word(bin), glb.len());
+
+        let mut empty = Vec::new();
+        write_glb(&mut empty, &[]).unwrap();
+        assert_eq!(empty.len(), 12 + 8 + 60);
+        assert!(std::str::from_utf8(&empty[20..])
+            .unwrap()
+            .starts_with("{\n  \"asset\": { \"version\": \"2.0\", \"generator\": \"rover\" }\n}\n"));
+    }
+}
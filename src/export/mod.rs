@@ -0,0 +1,227 @@
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+pub mod gltf;
+pub mod obj;
+pub mod off;
+
+//a vertex of the exported meshes, attributes that are not exported are zero
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: [f32; 4],
+    pub uv: [f32; 2],
+}
+
+impl Vertex {
+    //the bits of all values, vertices with the same key are shared by the triangles and lines
+    fn key(&self) -> [u32; 12] {
+        let mut key = [0; 12];
+        let values = self
+            .position
+            .iter()
+            .chain(&self.normal)
+            .chain(&self.color)
+            .chain(&self.uv);
+        for (k, v) in key.iter_mut().zip(values) {
+            *k = v.to_bits();
+        }
+        key
+    }
+}
+
+//which attributes besides the positions and colors a file format can store
+#[derive(Debug, Clone, Copy)]
+pub struct Attributes {
+    pub normals: bool,
+    pub uvs: bool,
+}
+
+/*
+    the triangles and lines of all meshes with shared vertices
+    the meshes built by MeshBuilder give each triangle its own vertices, when exporting, vertices that are equal in
+    all exported attributes are merged, eg. along the edges between the pieces of a stitched patch
+*/
+#[derive(Debug, Default)]
+pub struct IndexedMesh {
+    pub vertices: Vec<Vertex>,
+    pub triangles: Vec<[usize; 3]>,
+    pub lines: Vec<[usize; 2]>,
+}
+
+impl IndexedMesh {
+    pub fn from_meshes(meshes: &[Mesh], attributes: Attributes) -> Self {
+        let mut indexed = IndexedMesh::default();
+        let mut shared = HashMap::<[u32; 12], usize>::new();
+        for mesh in meshes {
+            let vertices = mesh_vertices(mesh, attributes);
+            let indices: Vec<usize> = match mesh.indices() {
+                Some(Indices::U16(indices)) => indices.iter().map(|i| *i as usize).collect(),
+                Some(Indices::U32(indices)) => indices.iter().map(|i| *i as usize).collect(),
+                None => (0..vertices.len()).collect(),
+            };
+            let mut index = |i: usize| {
+                let v: Vertex = vertices[i];
+                *shared.entry(v.key()).or_insert_with(|| {
+                    indexed.vertices.push(v);
+                    indexed.vertices.len() - 1
+                })
+            };
+            match mesh.primitive_topology() {
+                PrimitiveTopology::TriangleList => {
+                    let triangles: Vec<[usize; 3]> = indices
+                        .chunks_exact(3)
+                        .map(|t| [index(t[0]), index(t[1]), index(t[2])])
+                        .collect();
+                    indexed.triangles.extend(triangles);
+                }
+                PrimitiveTopology::LineList => {
+                    let lines: Vec<[usize; 2]> = indices.chunks_exact(2).map(|l| [index(l[0]), index(l[1])]).collect();
+                    indexed.lines.extend(lines);
+                }
+                _ => {}
+            }
+        }
+        indexed
+    }
+
+    //whether any vertex has a color other than the given one, ie. whether colors have to be written
+    pub fn is_colored(&self, default_color: Vec3) -> bool {
+        let default_color = [default_color.x, default_color.y, default_color.z, 1.];
+        self.vertices.iter().any(|v| v.color != default_color)
+    }
+}
+
+fn mesh_vertices(mesh: &Mesh, attributes: Attributes) -> Vec<Vertex> {
+    let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float32x3(positions)) => positions.clone(),
+        _ => Vec::new(),
+    };
+    let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+        Some(VertexAttributeValues::Float32x3(normals)) if attributes.normals => normals.clone(),
+        _ => Vec::new(),
+    };
+    let colors = match mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
+        Some(VertexAttributeValues::Float32x4(colors)) => colors.clone(),
+        _ => Vec::new(),
+    };
+    let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+        Some(VertexAttributeValues::Float32x2(uvs)) if attributes.uvs => uvs.clone(),
+        _ => Vec::new(),
+    };
+    let mut vertices = Vec::with_capacity(positions.len());
+    for (i, position) in positions.into_iter().enumerate() {
+        vertices.push(Vertex {
+            position: position,
+            normal: normals.get(i).copied().unwrap_or_default(),
+            color: colors.get(i).copied().unwrap_or([1.; 4]),
+            uv: uvs.get(i).copied().unwrap_or_default(),
+        });
+    }
+    vertices
+}
+
+//gltf files keep the meshes apart, eg. the triangles of polygons, the ones of patches and the lines of curves
+fn gltf_meshes(meshes: &[Mesh]) -> Vec<IndexedMesh> {
+    meshes
+        .iter()
+        .map(|m| IndexedMesh::from_meshes(std::slice::from_ref(m), gltf::ATTRIBUTES))
+        .collect()
+}
+
+/*
+    writes the meshes to a file whose format is chosen by its extension, uncolored meshes are those that only
+    have the default color, which is then left out if the format allows it
+*/
+pub fn write_file<P>(path: P, meshes: &[Mesh], default_color: Vec3) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let extension = path
+        .as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    match extension.as_deref() {
+        Some("off") => {
+            let mesh = IndexedMesh::from_meshes(meshes, off::ATTRIBUTES);
+            let mut writer = BufWriter::new(File::create(path)?);
+            off::write(&mut writer, &mesh, mesh.is_colored(default_color))
+        }
+        Some("obj") => {
+            let mesh = IndexedMesh::from_meshes(meshes, obj::ATTRIBUTES);
+            let mut writer = BufWriter::new(File::create(path)?);
+            obj::write(&mut writer, &mesh, mesh.is_colored(default_color))
+        }
+        //the buffer of a gltf file is written next to it, with the same name and a .bin extension
+        Some("gltf") => {
+            let meshes = gltf_meshes(meshes);
+            let bin_path = path.as_ref().with_extension("bin");
+            let bin_file = bin_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let mut writer = BufWriter::new(File::create(&path)?);
+            let mut bin = BufWriter::new(File::create(&bin_path)?);
+            gltf::write_gltf(&mut writer, &mut bin, &bin_file, &meshes)
+        }
+        Some("glb") => {
+            let meshes = gltf_meshes(meshes);
+            let mut writer = BufWriter::new(File::create(path)?);
+            gltf::write_glb(&mut writer, &meshes)
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "unknown file format '{}', the file name has to end with .off, .obj, .gltf or .glb",
+                path.as_ref().display()
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::Segment;
+    use crate::triangle::Triangle;
+
+    #[test]
+    fn shared_vertices() {
+        let square = vec![
+            Triangle::new([Vec3::ZERO, Vec3::X, Vec3::ONE], [Vec3::ONE; 3]),
+            Triangle::new([Vec3::ZERO, Vec3::ONE, Vec3::Y], [Vec3::ONE; 3]),
+        ];
+        let segments = vec![Segment::new([Vec3::ZERO, Vec3::Z], [Vec3::ONE, Vec3::X])];
+        let meshes = [
+            Triangle::triangle_list_to_mesh(square),
+            Segment::segment_list_to_mesh(segments),
+        ];
+        //the square is not planar, so the normals of its triangles differ
+        let with_normals = IndexedMesh::from_meshes(
+            &meshes,
+            Attributes {
+                normals: true,
+                uvs: true,
+            },
+        );
+        assert_eq!(with_normals.vertices.len(), 8);
+        let mesh = IndexedMesh::from_meshes(
+            &meshes,
+            Attributes {
+                normals: false,
+                uvs: false,
+            },
+        );
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.lines, vec![[0, 4]]);
+        assert_eq!(mesh.vertices[4].color, [1., 0., 0., 1.]);
+        assert!(mesh.is_colored(Vec3::ONE));
+        assert!(!IndexedMesh::from_meshes(&meshes[..1], off::ATTRIBUTES).is_colored(Vec3::ONE));
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::{self, Write};
+
+use super::{Attributes, IndexedMesh};
+
+pub const ATTRIBUTES: Attributes = Attributes {
+    normals: true,
+    uvs: true,
+};
+
+/*
+    writes the triangles and lines as a Wavefront OBJ file, with an rgb color after each position if colored is true,
+    OBJ has no alpha values, so transparency is lost
+    every vertex has its own texture coordinates and normal, so faces use the same index for all three of them
+*/
+pub fn write<W: Write>(writer: &mut W, mesh: &IndexedMesh, colored: bool) -> io::Result<()> {
+    for v in &mesh.vertices {
+        let [x, y, z] = v.position;
+        match colored {
+            true => {
+                let [r, g, b, _] = v.color;
+                writeln!(writer, "v {} {} {} {} {} {}", x, y, z, r, g, b)?;
+            }
+            false => writeln!(writer, "v {} {} {}", x, y, z)?,
+        }
+    }
+    for v in &mesh.vertices {
+        writeln!(writer, "vt {} {}", v.uv[0], v.uv[1])?;
+    }
+    for v in &mesh.vertices {
+        writeln!(writer, "vn {} {} {}", v.normal[0], v.normal[1], v.normal[2])?;
+    }
+    //indices start at 1
+    for [a, b, c] in &mesh.triangles {
+        writeln!(writer, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a + 1, b + 1, c + 1)?;
+    }
+    for [a, b] in &mesh.lines {
+        writeln!(writer, "l {} {}", a + 1, b + 1)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::MeshBuilder;
+    use crate::export;
+    use crate::segment::Segment;
+    use crate::triangle::Triangle;
+    use bevy::prelude::{Vec2, Vec3};
+
+    #[test]
+    fn write_obj() {
+        let triangle = Triangle::new([Vec3::ZERO, Vec3::X, Vec3::Y], [Vec3::X, Vec3::ONE, Vec3::ONE]).with_uvs([
+            Vec2::ZERO,
+            Vec2::X,
+            Vec2::Y,
+        ]);
+        let segment = Segment::new([Vec3::X, Vec3::Z], [Vec3::ONE; 2]);
+        let meshes = [
+            Triangle::triangle_list_to_mesh(vec![triangle]),
+            Segment::segment_list_to_mesh(vec![segment]),
+        ];
+        let mesh = IndexedMesh::from_meshes(&meshes, ATTRIBUTES);
+        let mut output = Vec::new();
+        write(&mut output, &mesh, true).unwrap();
+        let expected = "v 0 0 0 1 0 0\nv 1 0 0 1 1 1\nv 0 1 0 1 1 1\nv 1 0 0 1 1 1\nv 0 0 1 1 1 1\n\
+                        vt 0 0\nvt 1 0\nvt 0 1\nvt 0 0\nvt 0 0\n\
+                        vn 0 0 1\nvn 0 0 1\nvn 0 0 1\nvn 0 0 0\nvn 0 0 0\n\
+                        f 1/1/1 2/2/2 3/3/3\nl 4 5\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn read_written_file() {
+        let dir = std::env::temp_dir().join(format!("rover_obj_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("in.off"), dir.join("out.obj"));
+        std::fs::write(&input, "BEZ113\n0 0 0 1 0 0\n0 1 0 1 1 1\n").unwrap();
+        let (meshes, _) = MeshBuilder::new(Vec3::ONE, None, false)
+            .with_depth(1)
+            .parse_file(&input)
+            .unwrap();
+        export::write_file(&output, &meshes, Vec3::ONE).unwrap();
+        let written = std::fs::read_to_string(&output).unwrap();
+        assert!(written.starts_with("v 0 0 0\n"));
+        let (_, report) = MeshBuilder::new(Vec3::ONE, None, true).parse_file(&output).unwrap();
+        assert_eq!(
+            (report.sections[0].header.as_str(), report.sections[0].loaded),
+            ("OBJ", 8)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use std::io::{self, Write};
+
+use super::{Attributes, IndexedMesh};
+
+//OFF files only store positions and colors, the normals are computed again when the file is read
+pub const ATTRIBUTES: Attributes = Attributes {
+    normals: false,
+    uvs: false,
+};
+
+/*
+    writes the triangles as one OFF section, with rgba colors after each vertex if colored is true
+    lines can not be written to OFF files since faces need at least three vertices, they are left out
+*/
+pub fn write<W: Write>(writer: &mut W, mesh: &IndexedMesh, colored: bool) -> io::Result<()> {
+    writeln!(writer, "{}", if colored { "COFF" } else { "OFF" })?;
+    writeln!(writer, "{} {} 0", mesh.vertices.len(), mesh.triangles.len())?;
+    for v in &mesh.vertices {
+        let [x, y, z] = v.position;
+        match colored {
+            true => {
+                let [r, g, b, a] = v.color;
+                writeln!(writer, "{} {} {} {} {} {} {}", x, y, z, r, g, b, a)?;
+            }
+            false => writeln!(writer, "{} {} {}", x, y, z)?,
+        }
+    }
+    for [a, b, c] in &mesh.triangles {
+        writeln!(writer, "3 {} {} {}", a, b, c)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::MeshBuilder;
+    use crate::export;
+    use crate::triangle::Triangle;
+    use bevy::prelude::Vec3;
+
+    #[test]
+    fn write_off() {
+        let triangles = vec![
+            Triangle::new([Vec3::ZERO, Vec3::X, Vec3::Y], [Vec3::ONE; 3]),
+            Triangle::new(
+                [Vec3::X, Vec3::ONE, Vec3::Y],
+                [Vec3::ONE, Vec3::new(0.5, 0., 1.), Vec3::ONE],
+            )
+            .with_alphas([1., 0.25, 1.]),
+        ];
+        let mesh = IndexedMesh::from_meshes(&[Triangle::triangle_list_to_mesh(triangles)], ATTRIBUTES);
+        let mut output = Vec::new();
+        write(&mut output, &mesh, true).unwrap();
+        let expected =
+            "COFF\n4 2 0\n0 0 0 1 1 1 1\n1 0 0 1 1 1 1\n0 1 0 1 1 1 1\n1 1 1 0.5 0 1 0.25\n3 0 1 2\n3 1 3 2\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        let mut output = Vec::new();
+        write(&mut output, &mesh, false).unwrap();
+        assert!(String::from_utf8(output).unwrap().starts_with("OFF\n4 2 0\n0 0 0\n"));
+    }
+
+    #[test]
+    fn read_written_file() {
+        let dir = std::env::temp_dir().join(format!("rover_off_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("in.off"), dir.join("out.off"));
+        std::fs::write(
+            &input,
+            "CBEZ113\n0 0 0 1 0 0\n0 1 0 1 1 1\n1 0 0 1\n0 1 0 1\n0 0 1 1\n1 1 1 1\n",
+        )
+        .unwrap();
+        let (meshes, _) = MeshBuilder::new(Vec3::ONE, None, false)
+            .with_depth(2)
+            .parse_file(&input)
+            .unwrap();
+        export::write_file(&output, &meshes, Vec3::ONE).unwrap();
+        let written = std::fs::read_to_string(&output).unwrap();
+        //the 16 pieces of the patch have 25 distinct corners
+        assert!(written.starts_with("COFF\n25 32 0\n"));
+        let (meshes, report) = MeshBuilder::new(Vec3::ONE, None, true).parse_file(&output).unwrap();
+        assert_eq!(report.sections[0].loaded, 32);
+        let e = export::write_file(dir.join("out.stl"), &meshes, Vec3::ONE).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+use bevy::{
+    prelude::*,
+    render::mesh::{PrimitiveTopology, VertexAttributeValues},
+};
+use std::fmt;
+use std::fmt::Write;
+use Vec3 as Color;
+
+use crate::builder::ParseReport;
+use crate::error::ParseError;
+
+//statistics about the objects of a file and their meshes, printed by `rover info`
+#[derive(Debug)]
+pub struct SceneInfo {
+    pub file: String,
+    //the number of loaded objects of each kind, see ParseReport::primitives
+    pub primitives: Vec<(String, usize)>,
+    pub triangles: usize,
+    pub segments: usize,
+    //the smallest axis aligned box around all meshes as (min, max), None if nothing was loaded
+    pub bounding_box: Option<(Vec3, Vec3)>,
+    //whether any vertex has a color other than the default color
+    pub colored: bool,
+    pub transparent: bool,
+    //the errors of the objects that were skipped
+    pub warnings: Vec<ParseError>,
+}
+
+impl SceneInfo {
+    pub fn new(file: &str, meshes: &[Mesh], report: &ParseReport, default_color: Color) -> Self {
+        let mut info = SceneInfo {
+            file: file.to_string(),
+            primitives: report.primitives(),
+            triangles: 0,
+            segments: 0,
+            bounding_box: None,
+            colored: false,
+            transparent: false,
+            warnings: report.sections.iter().flat_map(|s| s.skipped.iter().cloned()).collect(),
+        };
+        let default_color = [default_color.x, default_color.y, default_color.z];
+        for mesh in meshes {
+            //the meshes are not indexed in a way that shares vertices, each triangle or line has its own vertices
+            let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+                Some(VertexAttributeValues::Float32x3(positions)) => positions,
+                _ => continue,
+            };
+            match mesh.primitive_topology() {
+                PrimitiveTopology::TriangleList => info.triangles += positions.len() / 3,
+                PrimitiveTopology::LineList => info.segments += positions.len() / 2,
+                _ => {}
+            }
+            for p in positions {
+                let p = Vec3::from(*p);
+                info.bounding_box = match info.bounding_box {
+                    Some((min, max)) => Some((min.min(p), max.max(p))),
+                    None => Some((p, p)),
+                };
+            }
+            if let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
+                info.colored |= colors.iter().any(|c| c[..3] != default_color);
+                info.transparent |= colors.iter().any(|c| c[3] < 1.);
+            }
+        }
+        info
+    }
+
+    //the same statistics as a json object, so that scripts do not have to parse the text output
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+        writeln!(json, "  \"file\": {},", json_string(&self.file)).unwrap();
+        json.push_str("  \"primitives\": [");
+        for (i, (name, count)) in self.primitives.iter().enumerate() {
+            let separator = if i > 0 { "," } else { "" };
+            write!(
+                json,
+                "{}\n    {{ \"type\": {}, \"count\": {} }}",
+                separator,
+                json_string(name),
+                count
+            )
+            .unwrap();
+        }
+        json.push_str(if self.primitives.is_empty() { "],\n" } else { "\n  ],\n" });
+        writeln!(json, "  \"triangles\": {},", self.triangles).unwrap();
+        writeln!(json, "  \"segments\": {},", self.segments).unwrap();
+        match self.bounding_box {
+            Some((min, max)) => writeln!(
+                json,
+                "  \"bounding_box\": {{ \"min\": {}, \"max\": {} }},",
+                json_vec3(min),
+                json_vec3(max)
+            )
+            .unwrap(),
+            None => json.push_str("  \"bounding_box\": null,\n"),
+        }
+        writeln!(json, "  \"colored\": {},", self.colored).unwrap();
+        writeln!(json, "  \"transparent\": {},", self.transparent).unwrap();
+        json.push_str("  \"warnings\": [");
+        for (i, e) in self.warnings.iter().enumerate() {
+            let separator = if i > 0 { "," } else { "" };
+            write!(json, "{}\n    {}", separator, json_string(&e.to_string())).unwrap();
+        }
+        json.push_str(if self.warnings.is_empty() { "]\n" } else { "\n  ]\n" });
+        json.push_str("}\n");
+        json
+    }
+}
+
+impl fmt::Display for SceneInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        writeln!(f, "file: {}", self.file)?;
+        for (name, count) in &self.primitives {
+            writeln!(f, "{}: {}", name, count)?;
+        }
+        writeln!(f, "triangles after tessellation: {}", self.triangles)?;
+        writeln!(f, "line segments: {}", self.segments)?;
+        match self.bounding_box {
+            Some((min, max)) => writeln!(f, "bounding box: {} to {}", min, max)?,
+            None => writeln!(f, "bounding box: none")?,
+        }
+        writeln!(f, "colored: {}", yes_no(self.colored))?;
+        writeln!(f, "transparent: {}", yes_no(self.transparent))?;
+        writeln!(f, "warnings: {}", self.warnings.len())?;
+        for e in &self.warnings {
+            writeln!(f, "    {}", e)?;
+        }
+        Ok(())
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+//json has no infinity or nan, they only come from broken input and are written as null
+fn json_number(x: f32) -> String {
+    match x.is_finite() {
+        true => x.to_string(),
+        false => String::from("null"),
+    }
+}
+
+fn json_vec3(v: Vec3) -> String {
+    format!("[{}, {}, {}]", json_number(v.x), json_number(v.y), json_number(v.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::MeshBuilder;
+
+    fn scene_info(input: &str) -> SceneInfo {
+        let path = std::env::temp_dir().join(format!("rover_info_{}.off", std::process::id()));
+        std::fs::write(&path, input).unwrap();
+        let (meshes, report) = MeshBuilder::new(Color::ONE, None, false).parse_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        SceneInfo::new("a.off", &meshes, &report, Color::ONE)
+    }
+
+    #[test]
+    fn statistics() {
+        let info = scene_info(
+            "OFF\n4 2 0\n0 0 0\n2 0 0\n2 1 0\n0 1 -1\n3 0 1 2\n3 0 2 3 1 0 0 0.5\n\
+             BEZ33\n0 0 0 1 1 0 2 1 0 3 0 4\nBEZ113\n0 0\n",
+        );
+        assert_eq!(
+            info.primitives,
+            vec![
+                (String::from("polygon"), 2),
+                (String::from("bezier curve of degree 3"), 1),
+                (String::from("bezier rectangle of degree 1x1"), 0),
+            ]
+        );
+        assert_eq!(info.triangles, 2);
+        assert!(info.segments > 0);
+        assert_eq!(info.bounding_box, Some((Vec3::new(0., 0., -1.), Vec3::new(3., 1., 4.))));
+        assert!(info.colored && info.transparent);
+        assert_eq!(info.warnings.len(), 1);
+        assert_eq!(info.warnings[0].section, "BEZ113");
+
+        let text = info.to_string();
+        assert!(text.contains("bezier curve of degree 3: 1\n"));
+        assert!(text.contains("bounding box: [0, 0, -1] to [3, 1, 4]\n"));
+        let json = info.to_json();
+        assert!(json.contains("{ \"type\": \"polygon\", \"count\": 2 }"));
+        assert!(json.contains("\"bounding_box\": { \"min\": [0, 0, -1], \"max\": [3, 1, 4] },"));
+        assert!(json.contains("\"colored\": true,"));
+    }
+
+    #[test]
+    fn empty_scene() {
+        let info = scene_info("# nothing here\n");
+        assert_eq!(info.triangles, 0);
+        assert_eq!(info.bounding_box, None);
+        assert!(!info.colored);
+        assert_eq!(
+            info.to_json(),
+            "{\n  \"file\": \"a.off\",\n  \"primitives\": [],\n  \"triangles\": 0,\n  \"segments\": 0,\n  \
+             \"bounding_box\": null,\n  \"colored\": false,\n  \"transparent\": false,\n  \"warnings\": []\n}\n"
+        );
+        assert_eq!(json_string("a \"b\"\\\n"), "\"a \\\"b\\\"\\\\\\n\"");
+    }
+}
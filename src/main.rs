@@ -1,21 +1,18 @@
-#![feature(generic_const_exprs)]
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
 /*
     this project uses const generics to create indiviudal structs for any degree of bezier curve/surface.
     since bezier curves use one more control point than their degree, and surfaces need a product / triangular number of ctrl points,
-    it is necessary to compute the array sizes at compile time from the generic parameter.
-    evaluating generic const expressions is a feature only available in the nightly version of rust and needs to be enabled manually.
-    as such it is possible that some syntax needs to be changed in the future in order for this to work correctly.
-    further, some trait bounds need to be explicitly stated:
-    for a bezier curve of degree N, an array of size N+1 is needed. in the (extremely unrealistic case) of N=usize::max,
-    such an array can not be allocated, therefore the trait bound
-        [(); N+1]:
-    is needed.
+    the array sizes would have to be computed from the generic parameter. this needs generic const expressions,
+    which are only available in the nightly version of rust, so the control points are stored in a Vec instead.
+    the constructors take an array whose size is a separate generic parameter, and check it against the degree at compile time.
+    typed rectangles and triangles evaluate and subdivide on arrays on the stack, sized for the largest degree, see BezierRectangle.
     note that the compiler cannot infer the degree of a bezier object only from an array size. it can however infer the type of control point.
     initialize a bezier curve of eg degree 2 like this:
         let b = BezierCurve::<_, 2>::new([1,2,3]);
+    objects read from a file have a degree only known at runtime, they are DynBezierRectangles, DynBezierTriangles and DynBezierCurves,
+    which share the algorithms on the control points with the typed ones.
 */
 
 /*
@@ -29,23 +26,33 @@
 //include all submodules so tests run
 mod bezier;
 mod builder;
+mod error;
+mod export;
+mod info;
 mod math;
+mod segment;
+mod stitching;
 mod subdivision;
+mod tokens;
 mod triangle;
+mod triangulation;
 mod util;
 
 //use bevy_fly_camera::{FlyCamera, FlyCameraPlugin};
 mod bevy_fly_camera;
 
 use bevy::{app::AppExit, input::keyboard::KeyboardInput, prelude::*};
-use clap::Parser;
+use clap::{CommandFactory, ErrorKind, Parser, Subcommand};
 
 #[derive(Parser)]
-#[clap(version, about, long_about = None)]
+#[clap(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// File containing objects to be displayed
     #[clap(parse(try_from_str=util::file_exists))]
-    path: String,
+    path: Option<String>,
 
     /// Background color in rgb hex format, eg. ffffff for white
     #[clap(short, long, parse(try_from_str=util::str_to_color))]
@@ -54,18 +61,121 @@ struct Args {
     /// Default color for objects that do not contain color data in rgb hex format
     #[clap(short, long, parse(try_from_str=util::str_to_color))]
     default_color: Option<Color>,
+
+    /// Subdivide each bezier patch until its control net deviates at most this distance from a flat patch,
+    /// instead of subdividing all patches uniformly
+    #[clap(short, long, parse(try_from_str=util::positive_float))]
+    tolerance: Option<f32>,
+
+    /// Abort on the first object that can not be read, instead of skipping it
+    #[clap(short, long)]
+    strict: bool,
+
+    /// PNG image that is mapped onto all objects according to their texture coordinates
+    #[clap(long, parse(try_from_str=util::file_exists))]
+    texture: Option<String>,
+}
+
+//without a subcommand rover displays the objects of a file, subcommands work without opening a window
+#[derive(Subcommand)]
+enum Command {
+    /// Print the number of objects of each type, the bounding box, the number of triangles and the skipped objects
+    Info(InfoArgs),
+    /// Subdivide the objects of a file like when displaying them and write the triangles to another file
+    Tessellate(TessellateArgs),
+}
+
+#[derive(clap::Args)]
+struct InfoArgs {
+    /// File containing the objects
+    #[clap(parse(try_from_str=util::file_exists))]
+    path: String,
+
+    /// Subdivide each bezier patch until it deviates at most this distance from a flat patch, as when displaying it
+    #[clap(short, long, parse(try_from_str=util::positive_float))]
+    tolerance: Option<f32>,
+
+    /// Fail on the first object that can not be read, instead of listing it as a warning
+    #[clap(short, long)]
+    strict: bool,
+
+    /// Print the statistics as json instead of text
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct TessellateArgs {
+    /// File containing the objects
+    #[clap(parse(try_from_str=util::file_exists))]
+    path: String,
+
+    /// File the triangles are written to, its format is chosen by the extension, .off, .obj, .gltf or .glb
+    #[clap(short, long)]
+    output: String,
+
+    /// Default color for objects that do not contain color data in rgb hex format
+    #[clap(short, long, parse(try_from_str=util::str_to_color))]
+    default_color: Option<Color>,
+
+    /// Subdivide each bezier patch until it deviates at most this distance from a flat patch
+    #[clap(short, long, conflicts_with = "depth", parse(try_from_str=util::positive_float))]
+    tolerance: Option<f32>,
+
+    /// Subdivide each bezier patch this many times into four pieces and each curve into two, at most 8 times
+    #[clap(long)]
+    depth: Option<u32>,
+
+    /// Abort on the first object that can not be read, instead of skipping it
+    #[clap(short, long)]
+    strict: bool,
 }
 
-impl FromWorld for Args {
-    fn from_world(world: &mut World) -> Self {
-        Args::parse()
+//the meshes read from the input file, they are moved into the scene by load_objects
+struct LoadedMeshes(Vec<Mesh>);
+
+const DEFAULT_COLOR: Vec3 = Vec3::new(0.8, 0.8, 0.8);
+
+//the default color given on the command line as Vec3, light gray if none was given
+fn to_vec3(color: Option<Color>) -> Vec3 {
+    match color {
+        Some(x) => Vec3::new(x.r(), x.g(), x.b()),
+        None => DEFAULT_COLOR,
+    }
+}
+
+//reads all objects of the file, on errors in strict mode or if the file can not be read rover exits
+fn load_meshes(builder: builder::MeshBuilder, path: &str) -> (Vec<Mesh>, builder::ParseReport) {
+    match builder.parse_file(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
     }
 }
 
 fn main() {
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::Info(info_args)) => return print_info(info_args),
+        Some(Command::Tessellate(tessellate_args)) => return tessellate(tessellate_args),
+        None => {}
+    }
+    let path = match &args.path {
+        Some(path) => path.clone(),
+        None => Args::command()
+            .error(ErrorKind::MissingRequiredArgument, "a file to display is required")
+            .exit(),
+    };
+    let builder = builder::MeshBuilder::new(to_vec3(args.default_color), args.tolerance, args.strict);
+    let (my_meshes, report) = load_meshes(builder, &path);
+    print!("{}", report);
+
     App::new()
         .insert_resource(Msaa { samples: 4 })
-        .init_resource::<Args>()
+        .insert_resource(args)
+        .insert_resource(LoadedMeshes(my_meshes))
         .add_plugins(DefaultPlugins)
         .add_startup_system(scene_setup)
         .add_startup_system(load_objects)
@@ -74,32 +184,58 @@ fn main() {
         .run();
 }
 
+fn print_info(args: &InfoArgs) {
+    let builder = builder::MeshBuilder::new(DEFAULT_COLOR, args.tolerance, args.strict);
+    let (meshes, report) = load_meshes(builder, &args.path);
+    let info = info::SceneInfo::new(&args.path, &meshes, &report, DEFAULT_COLOR);
+    match args.json {
+        true => print!("{}", info.to_json()),
+        false => print!("{}", info),
+    }
+}
+
+fn tessellate(args: &TessellateArgs) {
+    let default_color = to_vec3(args.default_color);
+    let mut builder = builder::MeshBuilder::new(default_color, args.tolerance, args.strict);
+    if let Some(depth) = args.depth {
+        builder = builder.with_depth(depth);
+    }
+    let (meshes, report) = load_meshes(builder, &args.path);
+    print!("{}", report);
+    if let Err(e) = export::write_file(&args.output, &meshes, default_color) {
+        eprintln!("error: can not write {}: {}", args.output, e);
+        std::process::exit(1);
+    }
+}
+
 fn load_objects(
     args: Res<Args>,
+    asset_server: Res<AssetServer>,
+    mut loaded: ResMut<LoadedMeshes>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    let default_color = match args.default_color {
-        Some(x) => x,
-        None => Color::rgb(0.8, 0.8, 0.8),
-    };
-    let default_color = Vec3::new(default_color.r(), default_color.g(), default_color.b());
-    let builder = builder::MeshBuilder::new(default_color);
-    let my_meshes = match builder.parse_file(&args.path) {
-        Ok(m) => m,
-        Err(e) => {
-            println!("An error occured while parsing the input file");
-            panic!();
-        }
-    };
-    for mesh in my_meshes {
+    //the asset server looks up relative paths in its asset folder, so the path is made absolute first
+    let texture: Option<Handle<Image>> = args.texture.as_ref().map(|path| {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+        asset_server.load(path)
+    });
+    for mesh in std::mem::take(&mut loaded.0) {
         let mut triangle_material = StandardMaterial::default();
         triangle_material.metallic = 0.;
         triangle_material.reflectance = 0.0;
         triangle_material.cull_mode = None;
         triangle_material.base_color = Color::WHITE; //lets 100% of vertex colors through
         triangle_material.double_sided = false; //for lighting on backside not sure which is right
+        triangle_material.base_color_texture = texture.clone(); //multiplied with the vertex colors
+        if triangle::is_transparent(&mesh) {
+            //vertex alphas are only taken into account when blending
+            triangle_material.alpha_mode = AlphaMode::Blend;
+        }
+        if segment::is_line_mesh(&mesh) {
+            triangle_material.unlit = true;
+        }
 
         commands.spawn_bundle(PbrBundle {
             mesh: meshes.add(mesh),
@@ -1,16 +1,41 @@
+use bevy::prelude::{Mat4, Vec3, Vec4};
 use std::ops::{Add, Mul};
 
+#[derive(Debug, Clone, Copy)]
 pub enum Axis2D {
     U,
     V,
 }
 
+//the sides of the unit square (u,v) in [0,1]x[0,1], eg. U0 is the side where u == 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side2D {
+    U0,
+    U1,
+    V0,
+    V1,
+}
+
 pub enum Axis3D {
     X,
     Y,
     Z,
 }
 
+/*
+    objects that can be moved by a 4x4 matrix, eg. the one of an INST
+    the control points of non-rational objects are projected after the transformation,
+    which is only exact for affine transformations, rational objects are transformed in homogeneous coordinates
+*/
+pub trait Transform {
+    fn transform(&mut self, m: Mat4);
+}
+
+//the matrix that transforms normals like m transforms points
+pub fn normal_matrix(m: Mat4) -> Mat4 {
+    m.inverse().transpose()
+}
+
 pub const fn triangular_number(n: usize) -> usize {
     n * (n + 1) / 2
 }
@@ -48,38 +73,140 @@ where
             z
     computes z by linear interpolation between x and y at parameter t
 */
-pub fn compute_triangular_scheme<T, const N: usize>(elements: &[T; N], t: f32) -> [T; triangular_number(N)]
+pub fn compute_triangular_scheme<T>(elements: &[T], t: f32) -> Vec<T>
 where
     T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
-    [(); triangular_number(N)]:,
 {
-    let mut row_len = N;
+    let mut row_len = elements.len();
     let mut row_offset_prev = 0;
-    let mut row_offset_curr = 0;
-    let mut triangle = [elements[0]; triangular_number(N)];
+    let mut triangle = Vec::<T>::with_capacity(triangular_number(row_len));
 
     //copy first row
-    for i in 0..N {
-        triangle[i] = elements[i];
-    }
-    row_offset_curr += row_len;
-    row_len -= 1;
+    triangle.extend_from_slice(elements);
+    row_len = row_len.saturating_sub(1);
 
     //apply scheme recursively starting from the copied row
     while row_len > 0 {
         for i in 0..row_len {
             let a = triangle[row_offset_prev + i];
             let b = triangle[row_offset_prev + i + 1];
-            triangle[row_offset_curr + i] = lerp(a, b, t);
+            triangle.push(lerp(a, b, t));
         }
-
-        row_offset_prev = row_offset_curr;
-        row_offset_curr += row_len;
+        row_offset_prev += row_len + 1;
         row_len -= 1;
     }
     triangle
 }
 
+/*
+    Computes the same scheme as compute_triangular_scheme in place, without storing the whole triangle
+    Afterwards first holds the first element of each row from the top, eg. a0 b0 c0 d0,
+    and elements holds the last element of each row from the bottom, eg. d0 c1 b2 a3
+    For the control points of a bezier curve these are the control points of the two halves split at t
+    first needs to have the same length as elements
+*/
+pub fn triangular_scheme_edges<T>(elements: &mut [T], first: &mut [T], t: f32)
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let len = elements.len();
+    for row in 0..len {
+        first[row] = elements[0];
+        //the next row overwrites the current one from the front, its last element stays in place
+        for i in 0..len - row - 1 {
+            elements[i] = lerp(elements[i], elements[i + 1], t);
+        }
+    }
+}
+
+/*
+    Reduces the control points of a bezier curve in place
+    and returns the point on the curve and the derivative at t, like point_and_derivative does for a full triangle
+*/
+pub fn point_and_derivative_in_place<T>(elements: &mut [T], t: f32) -> (T, T)
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let degree = elements.len() - 1;
+    if degree == 0 {
+        return (elements[0], elements[0] * 0.);
+    }
+    for row_len in (2..degree + 1).rev() {
+        for i in 0..row_len {
+            elements[i] = lerp(elements[i], elements[i + 1], t);
+        }
+    }
+    let derivative = (elements[1] + elements[0] * -1.) * degree as f32;
+    (lerp(elements[0], elements[1], t), derivative)
+}
+
+/*
+    Takes a triangle computed by compute_triangular_scheme from the control points of a bezier curve of the given degree
+    and returns the point on the curve as well as the derivative of the curve at the parameter t used for the scheme
+    The derivative is computed from the second to last row:
+        ...
+        y0  y1
+        \  /
+         z0
+    where z0 is the point on the curve and degree * (y1 - y0) is the derivative
+*/
+pub fn point_and_derivative<T>(triangle: &[T], degree: usize) -> (T, T)
+where
+    T: Copy + Add<T, Output = T> + Mul<f32, Output = T>,
+{
+    let last = triangle.len() - 1;
+    let point = triangle[last];
+    if degree == 0 {
+        return (point, point * 0.);
+    }
+    let derivative = (triangle[last - 1] + triangle[last - 2] * -1.) * degree as f32;
+    (point, derivative)
+}
+
+/*
+    The unit normal of a surface at (u,v) in [0,1]x[0,1] from its partial derivatives du, dv and the mixed derivative duv
+    On a collapsed boundary (eg. at the pole of a sphere) one of the partial derivatives vanishes.
+    The normal is then the limit taken from the inside of the surface, which is computed from the mixed derivative:
+    if the u-derivative vanishes along v == 0, then near the boundary du(u,v) ~ v * duv(u,0)
+    and the normal points in direction duv x dv
+    Returns zero if no normal can be found this way
+*/
+pub fn surface_normal(u: f32, v: f32, du: Vec3, dv: Vec3, duv: Vec3) -> Vec3 {
+    let tolerance = du.length().max(dv.length()) * 1e-4;
+    //signs of the directions pointing into the surface
+    let inward_u = if u < 0.5 { 1. } else { -1. };
+    let inward_v = if v < 0.5 { 1. } else { -1. };
+    let n = if du.length() <= tolerance {
+        duv.cross(dv) * inward_v
+    } else if dv.length() <= tolerance {
+        du.cross(duv) * inward_u
+    } else {
+        du.cross(dv)
+    };
+    n.normalize_or_zero()
+}
+
+//the point a homogeneous point (x, y, z, w) stands for
+pub fn project(h: Vec4) -> Vec3 {
+    h.truncate() / h.w
+}
+
+/*
+    Projects a point h of a rational surface in homogeneous coordinates together with its derivatives hu, hv and huv
+    The surface is P = p / w for h = (p, w), so differentiating p = P * w gives
+        p_u = P_u * w + P * w_u
+        p_uv = P_uv * w + P_u * w_v + P_v * w_u + P * w_uv
+    which is solved for the derivatives of P
+    Returns the point, its derivatives in u and in v direction and the mixed second derivative
+*/
+pub fn project_derivatives(h: Vec4, hu: Vec4, hv: Vec4, huv: Vec4) -> (Vec3, Vec3, Vec3, Vec3) {
+    let point = project(h);
+    let du = (hu.truncate() - point * hu.w) / h.w;
+    let dv = (hv.truncate() - point * hv.w) / h.w;
+    let duv = (huv.truncate() - du * hv.w - dv * hu.w - point * huv.w) / h.w;
+    (point, du, dv, duv)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -131,4 +258,28 @@ mod tests {
         let expected = [0., 4., 6., 9., 2., 5., 7.5, 3.5, 6.25, 4.875];
         assert_eq!(res, expected);
     }
+
+    #[test]
+    fn triangular_scheme_in_place() {
+        let mut row = [0., 4., 6., 9.];
+        let mut first = [0.; 4];
+        triangular_scheme_edges(&mut row, &mut first, 0.5);
+        assert_eq!(first, [0., 2., 3.5, 4.875]);
+        assert_eq!(row, [4.875, 6.25, 7.5, 9.]);
+
+        let mut row = [0., 0., 3.];
+        assert_eq!(point_and_derivative_in_place(&mut row, 0.5), (0.75, 3.));
+        assert_eq!(point_and_derivative_in_place(&mut [2.], 0.5), (2., 0.));
+    }
+
+    #[test]
+    fn scheme_point_and_derivative() {
+        //b(t) = 3t^2 for control points 0, 0, 3
+        let row = [0., 0., 3.];
+        let res = compute_triangular_scheme(&row, 0.5);
+        assert_eq!(point_and_derivative(&res, 2), (0.75, 3.));
+
+        let res = compute_triangular_scheme(&[2.], 0.5);
+        assert_eq!(point_and_derivative(&res, 0), (2., 0.));
+    }
 }
@@ -0,0 +1,101 @@
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+};
+use Vec3 as Color;
+
+pub trait ToSegments {
+    fn to_segments(&self) -> Vec<Segment>;
+}
+
+//a line segment with a color at each end, curves are drawn as lists of segments
+#[derive(Debug)]
+pub struct Segment {
+    points: [Vec3; 2],
+    colors: [Color; 2],
+    alphas: [f32; 2],
+}
+
+impl Segment {
+    pub fn new(points: [Vec3; 2], colors: [Color; 2]) -> Self {
+        Segment {
+            points: points,
+            colors: colors,
+            alphas: [1.; 2],
+        }
+    }
+
+    pub fn with_alphas(mut self, alphas: [f32; 2]) -> Self {
+        self.alphas = alphas;
+        self
+    }
+
+    pub fn points(&self) -> [Vec3; 2] {
+        self.points
+    }
+
+    pub fn colors(&self) -> [Color; 2] {
+        self.colors
+    }
+
+    /*
+        the segments are drawn as a line list, ie. each two vertices form a line
+        lines have no normals or texture coordinates, the attributes are still set because the pbr pipeline expects them
+    */
+    pub fn segment_list_to_mesh(segments: Vec<Segment>) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+        let mut positions = Vec::<[f32; 3]>::new();
+        let mut colors = Vec::<[f32; 4]>::new();
+
+        for s in &segments {
+            for i in 0..2 {
+                positions.push([s.points[i].x, s.points[i].y, s.points[i].z]);
+                colors.push([s.colors[i].x, s.colors[i].y, s.colors[i].z, s.alphas[i]]);
+            }
+        }
+        let indices = (0..positions.len() as u32).collect();
+        let normals = vec![[0f32; 3]; positions.len()];
+        let uvs = vec![[0f32; 2]; positions.len()];
+
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh
+    }
+}
+
+//whether the mesh consists of lines, which are drawn unlit since they have no normals
+pub fn is_line_mesh(mesh: &Mesh) -> bool {
+    matches!(
+        mesh.primitive_topology(),
+        PrimitiveTopology::LineList | PrimitiveTopology::LineStrip
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::mesh::VertexAttributeValues;
+
+    #[test]
+    fn segment_mesh() {
+        let segments = vec![
+            Segment::new([Vec3::ZERO, Vec3::X], [Color::X, Color::Y]),
+            Segment::new([Vec3::X, Vec3::Y], [Color::Y, Color::Z]).with_alphas([1., 0.5]),
+        ];
+        let mesh = Segment::segment_list_to_mesh(segments);
+        assert!(is_line_mesh(&mesh));
+        match mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
+            Some(VertexAttributeValues::Float32x4(colors)) => {
+                assert_eq!(colors.len(), 4);
+                assert_eq!(colors[3], [0., 0., 1., 0.5]);
+            }
+            _ => panic!("mesh has no colors"),
+        }
+        assert!(!is_line_mesh(&crate::triangle::Triangle::triangle_list_to_mesh(
+            Vec::new()
+        )));
+    }
+}
@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+
+use crate::bezier::curve;
+use crate::math::Side2D;
+use crate::triangle::Triangle;
+use bevy::prelude::{Vec2, Vec3};
+use Vec3 as Color;
+
+/*
+    Patches that are subdivided to different depths do not share the same vertices along their common boundaries.
+    Drawing every piece as two triangles then leads to T-junctions and visible cracks,
+    both between neighbouring patches and between pieces of the same patch.
+    A StitchSet avoids this by triangulating every piece with all vertices that lie on its sides:
+        - the corners of all other pieces of the same patch
+        - the vertices of all neighbouring patches along a shared boundary
+    Two patches are neighbours along a side if these sides are the same bezier curve, possibly in reverse order.
+    The curves may have different degrees, eg. the side of a bilinear patch next to a bicubic one,
+    so they are elevated to the same degree before their control points are compared.
+    Triangular patches take part as rectangular patches with a side collapsed into a point, see BezierTriangle.
+    All vertices are evaluated on the original patch at their (u,v) parameter, except for the vertices on a shared
+    boundary, which are evaluated on the first patch of that boundary. Since pieces are created by halving,
+    the parameters are exact and both sides of a shared boundary get exactly the same positions,
+    so the resulting mesh is watertight.
+*/
+
+//a surface that can be tessellated by a StitchSet
+pub trait Surface {
+    fn position(&self, u: f32, v: f32) -> Vec3;
+    fn normal(&self, u: f32, v: f32) -> Vec3;
+    fn color(&self, u: f32, v: f32) -> Color;
+    fn alpha(&self, u: f32, v: f32) -> f32;
+    //texture coordinates at (u,v)
+    fn uv(&self, u: f32, v: f32) -> Vec2;
+    //control points along the given side, ordered by increasing parameter
+    fn boundary(&self, side: Side2D) -> Vec<Vec3>;
+}
+
+//everything a triangle needs to know about one of its vertices
+#[derive(Clone, Copy)]
+struct Vertex {
+    position: Vec3,
+    color: Color,
+    alpha: f32,
+    normal: Vec3,
+    uv: Vec2,
+}
+
+const SIDES: [Side2D; 4] = [Side2D::U0, Side2D::U1, Side2D::V0, Side2D::V1];
+
+//boundary curves are the same if their control points are this close, relative to the length of the control polygon
+const BOUNDARY_TOLERANCE: f32 = 1e-5;
+
+//a side of a patch in a StitchSet as (patch index, side, reversed), see StitchSet::shared_boundaries
+type SharedSide = (usize, Side2D, bool);
+
+//the parameter along a side, in the order of Surface::boundary
+fn side_parameter(side: Side2D, p: Vec2) -> Option<f32> {
+    match side {
+        Side2D::U0 if p.x == 0. => Some(p.y),
+        Side2D::U1 if p.x == 1. => Some(p.y),
+        Side2D::V0 if p.y == 0. => Some(p.x),
+        Side2D::V1 if p.y == 1. => Some(p.x),
+        _ => None,
+    }
+}
+
+//the point on a side at parameter t, the inverse of side_parameter
+fn side_point(side: Side2D, t: f32) -> Vec2 {
+    match side {
+        Side2D::U0 => Vec2::new(0., t),
+        Side2D::U1 => Vec2::new(1., t),
+        Side2D::V0 => Vec2::new(t, 0.),
+        Side2D::V1 => Vec2::new(t, 1.),
+    }
+}
+
+/*
+    whether two boundaries are the same curve, Some(true) if b runs in the opposite direction of a
+    both are elevated to the higher of their degrees, which does not change the curves
+*/
+fn same_curve(a: &[Vec3], b: &[Vec3]) -> Option<bool> {
+    let degree = a.len().max(b.len()) - 1;
+    let (a, b) = (curve::elevate(a, degree), curve::elevate(b, degree));
+    let length: f32 = a.windows(2).map(|w| w[0].distance(w[1])).sum();
+    let close = |p: &Vec3, q: &Vec3| p.distance(*q) <= BOUNDARY_TOLERANCE * length;
+    if a.iter().zip(b.iter()).all(|(p, q)| close(p, q)) {
+        Some(false)
+    } else if a.iter().zip(b.iter().rev()).all(|(p, q)| close(p, q)) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+//the pieces of a patch that is split depth times into four, see StitchSet::add
+pub fn uniform_domains(depth: u32) -> Vec<[Vec2; 2]> {
+    let n = 1usize << depth;
+    let step = 1. / n as f32;
+    let mut domains = Vec::with_capacity(n * n);
+    for j in 0..n {
+        for i in 0..n {
+            let start = Vec2::new(i as f32, j as f32) * step;
+            domains.push([start, start + Vec2::splat(step)]);
+        }
+    }
+    domains
+}
+
+/*
+    The parameters of all vertices of one patch, sorted by the lines they lie on
+    on_u[u] contains the v parameters of all vertices on the line with constant u, on_v[v] the other way round
+    The lines are keyed by the bits of the constant parameter
+*/
+#[derive(Default)]
+struct Lines {
+    on_u: HashMap<u32, Vec<f32>>,
+    on_v: HashMap<u32, Vec<f32>>,
+}
+
+impl Lines {
+    fn insert(&mut self, p: Vec2) {
+        self.on_u.entry(p.x.to_bits()).or_default().push(p.y);
+        self.on_v.entry(p.y.to_bits()).or_default().push(p.x);
+    }
+
+    fn line(&mut self, side: Side2D) -> &mut Vec<f32> {
+        match side {
+            Side2D::U0 => self.on_u.entry(0f32.to_bits()).or_default(),
+            Side2D::U1 => self.on_u.entry(1f32.to_bits()).or_default(),
+            Side2D::V0 => self.on_v.entry(0f32.to_bits()).or_default(),
+            Side2D::V1 => self.on_v.entry(1f32.to_bits()).or_default(),
+        }
+    }
+
+    fn sort(&mut self) {
+        for line in self.on_u.values_mut().chain(self.on_v.values_mut()) {
+            line.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            line.dedup();
+        }
+    }
+
+    //parameters strictly between a and b on the given line, in increasing order
+    fn between(line: Option<&Vec<f32>>, a: f32, b: f32) -> Vec<f32> {
+        match line {
+            Some(l) => l.iter().copied().filter(|t| a < *t && *t < b).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    //the boundary of the piece covering [start, end] with all vertices on its sides, counterclockwise in (u,v)
+    fn piece_outline(&self, start: Vec2, end: Vec2) -> Vec<Vec2> {
+        let on_u = |u: f32| self.on_u.get(&u.to_bits());
+        let on_v = |v: f32| self.on_v.get(&v.to_bits());
+        let mut outline = vec![start];
+        for u in Lines::between(on_v(start.y), start.x, end.x) {
+            outline.push(Vec2::new(u, start.y));
+        }
+        outline.push(Vec2::new(end.x, start.y));
+        for v in Lines::between(on_u(end.x), start.y, end.y) {
+            outline.push(Vec2::new(end.x, v));
+        }
+        outline.push(end);
+        for u in Lines::between(on_v(end.y), start.x, end.x).into_iter().rev() {
+            outline.push(Vec2::new(u, end.y));
+        }
+        outline.push(Vec2::new(start.x, end.y));
+        for v in Lines::between(on_u(start.x), start.y, end.y).into_iter().rev() {
+            outline.push(Vec2::new(start.x, v));
+        }
+        outline
+    }
+}
+
+pub struct StitchSet<'a> {
+    patches: Vec<&'a dyn Surface>,
+    domains: Vec<Vec<[Vec2; 2]>>,
+}
+
+impl<'a> StitchSet<'a> {
+    pub fn new() -> Self {
+        StitchSet {
+            patches: Vec::new(),
+            domains: Vec::new(),
+        }
+    }
+
+    //adds a patch together with the domains of the pieces it has been subdivided into
+    pub fn add(&mut self, patch: &'a dyn Surface, domains: Vec<[Vec2; 2]>) {
+        self.patches.push(patch);
+        self.domains.push(domains);
+    }
+
+    pub fn to_triangles(&self) -> Vec<Triangle<Vec3>> {
+        let mut lines: Vec<Lines> = self
+            .domains
+            .iter()
+            .map(|domains| {
+                let mut l = Lines::default();
+                for [start, end] in domains {
+                    l.insert(*start);
+                    l.insert(Vec2::new(end.x, start.y));
+                    l.insert(Vec2::new(start.x, end.y));
+                    l.insert(*end);
+                }
+                l
+            })
+            .collect();
+
+        //the vertices on the sides of each patch that are evaluated on the first patch of a shared boundary
+        let shared = self.shared_boundaries();
+        let mut owners = vec![[None; 4]; self.patches.len()];
+        for group in &shared {
+            let (owner, owner_side, _) = group[0];
+            for (k, side, reversed) in &group[1..] {
+                owners[*k][side_index(*side)] = Some((owner, owner_side, *reversed));
+            }
+        }
+
+        //exchange the vertices along shared boundaries, in the parameter direction of the first boundary found
+        for group in &shared {
+            let mut params = Vec::<f32>::new();
+            for (k, side, reversed) in group {
+                for t in lines[*k].line(*side).iter() {
+                    params.push(if *reversed { 1. - t } else { *t });
+                }
+            }
+            for (k, side, reversed) in group {
+                let line = lines[*k].line(*side);
+                line.extend(params.iter().map(|t| if *reversed { 1. - t } else { *t }));
+            }
+        }
+
+        let mut triangles = Vec::<Triangle<Vec3>>::new();
+        for (k, patch) in self.patches.iter().enumerate() {
+            lines[k].sort();
+            let mut cache = HashMap::<(u32, u32), Vertex>::new();
+            let mut vertex = |p: Vec2| {
+                *cache.entry((p.x.to_bits(), p.y.to_bits())).or_insert_with(|| {
+                    let (u, v) = (p.x, p.y);
+                    Vertex {
+                        position: self
+                            .shared_position(&owners[k], p)
+                            .unwrap_or_else(|| patch.position(u, v)),
+                        color: patch.color(u, v),
+                        alpha: patch.alpha(u, v),
+                        normal: patch.normal(u, v),
+                        uv: patch.uv(u, v),
+                    }
+                })
+            };
+            let mut push = |a: Vec2, b: Vec2, c: Vec2| {
+                let [a, b, c] = [vertex(a), vertex(b), vertex(c)];
+                //pieces along a side that is collapsed into a point have triangles without area
+                if a.position == b.position || b.position == c.position || c.position == a.position {
+                    return;
+                }
+                let triangle = Triangle::new_with_normals(
+                    [a.position, b.position, c.position],
+                    [a.color, b.color, c.color],
+                    [a.normal, b.normal, c.normal],
+                );
+                triangles.push(
+                    triangle
+                        .with_alphas([a.alpha, b.alpha, c.alpha])
+                        .with_uvs([a.uv, b.uv, c.uv]),
+                );
+            };
+
+            for [start, end] in &self.domains[k] {
+                let outline = lines[k].piece_outline(*start, *end);
+                if outline.len() == 4 {
+                    push(outline[0], outline[1], outline[3]);
+                    push(outline[1], outline[2], outline[3]);
+                } else {
+                    //a fan around the center of the piece includes all additional vertices
+                    let center = (*start + *end) * 0.5;
+                    for i in 0..outline.len() {
+                        push(center, outline[i], outline[(i + 1) % outline.len()]);
+                    }
+                }
+            }
+        }
+        triangles
+    }
+
+    /*
+        groups the sides of all patches that are the same boundary curve, see same_curve
+        each side is stored as (patch index, side, reversed) where reversed is set if the side runs
+        in the opposite direction of the first side of its group, only groups with more than one side are returned
+        the end points of shared sides are exactly the same, so only sides with the same end points are compared
+        sides that are collapsed into a single point are left out, they do not have any cracks
+    */
+    fn shared_boundaries(&self) -> Vec<Vec<SharedSide>> {
+        let key = |p: Vec3| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()];
+        let mut candidates = HashMap::<[[u32; 3]; 2], Vec<(usize, Side2D, Vec<Vec3>)>>::new();
+        for (k, patch) in self.patches.iter().enumerate() {
+            for side in SIDES {
+                let points = patch.boundary(side);
+                if points.iter().all(|p| *p == points[0]) {
+                    continue;
+                }
+                let mut ends = [key(points[0]), key(points[points.len() - 1])];
+                ends.sort();
+                candidates.entry(ends).or_default().push((k, side, points));
+            }
+        }
+
+        let mut shared = Vec::new();
+        for sides in candidates.values().filter(|sides| sides.len() > 1) {
+            let mut groups = Vec::<(&[Vec3], Vec<SharedSide>)>::new();
+            for (k, side, points) in sides {
+                let found = groups
+                    .iter_mut()
+                    .find_map(|(first, group)| same_curve(first, points).map(|reversed| (group, reversed)));
+                match found {
+                    Some((group, reversed)) => group.push((*k, *side, reversed)),
+                    None => groups.push((points, vec![(*k, *side, false)])),
+                }
+            }
+            shared.extend(
+                groups
+                    .into_iter()
+                    .map(|(_, group)| group)
+                    .filter(|group| group.len() > 1),
+            );
+        }
+        shared
+    }
+
+    //the position of a vertex at p on a shared boundary, evaluated on the first patch of that boundary
+    fn shared_position(&self, owners: &[Option<SharedSide>; 4], p: Vec2) -> Option<Vec3> {
+        for side in SIDES {
+            let owner = owners[side_index(side)];
+            if let (Some(t), Some((owner, owner_side, reversed))) = (side_parameter(side, p), owner) {
+                let q = side_point(owner_side, if reversed { 1. - t } else { t });
+                return Some(self.patches[owner].position(q.x, q.y));
+            }
+        }
+        None
+    }
+}
+
+fn side_index(side: Side2D) -> usize {
+    SIDES.iter().position(|s| *s == side).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bezier::rectangle::BezierRectangle;
+    use crate::bezier::triangle::BezierTriangle;
+    use crate::subdivision::SubdivisionSet;
+
+    const STEPS: [f32; 4] = [0., 0.25, 0.75, 1.];
+    const HEIGHTS: [f32; 4] = [0., 1., 1., 0.];
+
+    //a bicubic patch over [x, x+1]x[0,1] whose sides at x and x+1 are curved in z direction
+    fn patch(x: f32) -> BezierRectangle<Vec3, 3, 3> {
+        let mut pts = [Vec3::ZERO; 16];
+        for j in 0..4 {
+            for i in 0..4 {
+                pts[j * 4 + i] = Vec3::new(x + STEPS[i], STEPS[j], HEIGHTS[j]);
+            }
+        }
+        BezierRectangle::new(pts, [Color::ONE; 4])
+    }
+
+    fn pieces(patch: &BezierRectangle<Vec3, 3, 3>, rounds: usize) -> Vec<[Vec2; 2]> {
+        let mut subdiv = SubdivisionSet::new();
+        subdiv.elements = vec![patch.clone()];
+        for _ in 0..rounds {
+            subdiv.elements = subdiv.elements.iter().flat_map(|e| e.subdivide_cross()).collect();
+        }
+        subdiv.elements.iter().map(|e| e.domain()).collect()
+    }
+
+    //edges that are only used by a single triangle
+    fn open_edges(triangles: &Vec<Triangle<Vec3>>) -> Vec<(Vec3, Vec3)> {
+        let key = |p: Vec3| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()];
+        let mut count = HashMap::<([u32; 3], [u32; 3]), (usize, Vec3, Vec3)>::new();
+        for t in triangles {
+            let p = t.points();
+            for i in 0..3 {
+                let (a, b) = (p[i], p[(i + 1) % 3]);
+                let k = if key(a) < key(b) {
+                    (key(a), key(b))
+                } else {
+                    (key(b), key(a))
+                };
+                count.entry(k).or_insert((0, a, b)).0 += 1;
+            }
+        }
+        count
+            .values()
+            .filter(|(c, _, _)| *c == 1)
+            .map(|(_, a, b)| (*a, *b))
+            .collect()
+    }
+
+    #[test]
+    fn stitch_neighbours_with_different_depths() {
+        let left = patch(0.);
+        let right = patch(1.);
+        let mut stitch = StitchSet::new();
+        stitch.add(&left, pieces(&left, 3));
+        stitch.add(&right, pieces(&right, 1));
+        let triangles = stitch.to_triangles();
+
+        //the only open edges are on the outer boundary of both patches
+        for (a, b) in open_edges(&triangles) {
+            assert!(
+                !(a.x == 1. && b.x == 1.),
+                "crack along the shared boundary at {} {}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn stitch_pieces_of_one_patch() {
+        let surf = patch(0.);
+        let mut domains = pieces(&surf, 1);
+        //split only the first quarter once more
+        let first = domains.remove(0);
+        let mid = (first[0] + first[1]) * 0.5;
+        domains.push([first[0], mid]);
+        domains.push([Vec2::new(mid.x, first[0].y), Vec2::new(first[1].x, mid.y)]);
+        domains.push([Vec2::new(first[0].x, mid.y), Vec2::new(mid.x, first[1].y)]);
+        domains.push([mid, first[1]]);
+
+        let mut stitch = StitchSet::new();
+        stitch.add(&surf, domains);
+        let triangles = stitch.to_triangles();
+
+        //all open edges lie on the boundary of the patch
+        for (a, b) in open_edges(&triangles) {
+            let on_boundary = |p: Vec3| p.x == 0. || p.x == 1. || p.y == 0. || p.y == 1.;
+            assert!(
+                on_boundary(a) && on_boundary(b),
+                "crack inside the patch at {} {}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn stitch_reversed_neighbour() {
+        //the right patch is rotated by 180 degrees, so its shared boundary is stored in reverse order
+        let left = patch(0.);
+        let mut pts = [Vec3::ZERO; 16];
+        for j in 0..4 {
+            for i in 0..4 {
+                pts[j * 4 + i] = Vec3::new(2. - STEPS[i], 1. - STEPS[j], HEIGHTS[j]);
+            }
+        }
+        let right = BezierRectangle::<Vec3, 3, 3>::new(pts, [Color::ONE; 4]);
+        let mut stitch = StitchSet::new();
+        stitch.add(&left, pieces(&left, 2));
+        stitch.add(&right, pieces(&right, 0));
+        let triangles = stitch.to_triangles();
+
+        for (a, b) in open_edges(&triangles) {
+            assert!(
+                !(a.x == 1. && b.x == 1.),
+                "crack along the shared boundary at {} {}",
+                a,
+                b
+            );
+        }
+    }
+
+    //the flat bilinear patch over [x0, x1]x[y0, y1]
+    fn bilinear(x0: f32, x1: f32, y0: f32, y1: f32) -> BezierRectangle<Vec3, 1, 1> {
+        let pts = [
+            Vec3::new(x0, y0, 0.),
+            Vec3::new(x1, y0, 0.),
+            Vec3::new(x0, y1, 0.),
+            Vec3::new(x1, y1, 0.),
+        ];
+        BezierRectangle::new(pts, [Color::ONE; 4])
+    }
+
+    #[test]
+    fn stitch_neighbours_with_different_degrees() {
+        //a bicubic patch over [0,1]x[0,1] with a bump in the middle, its sides are straight lines of degree 3
+        let mut pts = [Vec3::ZERO; 16];
+        for j in 0..4 {
+            for i in 0..4 {
+                let height = if (1..3).contains(&i) && (1..3).contains(&j) {
+                    1.
+                } else {
+                    0.
+                };
+                pts[j * 4 + i] = Vec3::new(i as f32 / 3., j as f32 / 3., height);
+            }
+        }
+        let left = BezierRectangle::<Vec3, 3, 3>::new(pts, [Color::ONE; 4]);
+        //a bilinear patch next to it, whose side at x == 1 is the same line of degree 1
+        let right = bilinear(1., 2., 0., 1.);
+        let mut stitch = StitchSet::new();
+        stitch.add(&left, pieces(&left, 2));
+        stitch.add(&right, uniform_domains(0));
+        assert_eq!(stitch.shared_boundaries().len(), 1);
+        let triangles = stitch.to_triangles();
+
+        for (a, b) in open_edges(&triangles) {
+            assert!(
+                !(a.x == 1. && b.x == 1.),
+                "crack along the shared boundary at {} {}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn stitch_triangular_patch() {
+        //a flat cubic triangle over the corners (0,0), (1,0) and (0,1), above a bilinear patch over [0,1]x[-1,0]
+        let mut pts = [Vec3::ZERO; 10];
+        for j in 0..4 {
+            for i in 0..4 - j {
+                pts[crate::bezier::triangle::net_index(3, i, j)] = Vec3::new(i as f32 / 3., j as f32 / 3., 0.);
+            }
+        }
+        let triangle = BezierTriangle::<Vec3, 3>::new(pts, [Color::ONE; 3]);
+        let below = bilinear(0., 1., -1., 0.);
+        let mut stitch = StitchSet::new();
+        stitch.add(&triangle, uniform_domains(2));
+        stitch.add(&below, uniform_domains(0));
+        let triangles = stitch.to_triangles();
+
+        //the pieces of the collapsed side do not leave triangles without area
+        for t in &triangles {
+            let [a, b, c] = t.points();
+            assert!((b - a).cross(c - a).length() > 0.);
+        }
+        //all open edges lie on the outline of both patches
+        let on_outline = |p: Vec3| p.x == 0. || p.y == -1. || (p.x == 1. && p.y <= 0.) || (p.x + p.y - 1.).abs() < 1e-6;
+        for (a, b) in open_edges(&triangles) {
+            assert!(
+                !(a.y == 0. && b.y == 0.),
+                "crack along the shared boundary at {} {}",
+                a,
+                b
+            );
+            assert!(
+                on_outline(a) && on_outline(b),
+                "crack inside the patches at {} {}",
+                a,
+                b
+            );
+        }
+    }
+}
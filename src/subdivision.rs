@@ -1,5 +1,10 @@
+use crate::bezier::curve::BezierCurve;
+use crate::bezier::dyn_curve::DynBezierCurve;
+use crate::bezier::dyn_rectangle::DynBezierRectangle;
 use crate::bezier::rectangle::BezierRectangle;
-use crate::math;
+use crate::bezier::triangle::BezierTriangle;
+use crate::builder::MAX_DEPTH;
+use crate::segment::{Segment, ToSegments};
 use crate::triangle::{ToTriangle, Triangle};
 use bevy::prelude::Vec3;
 
@@ -9,6 +14,17 @@ pub trait Subdivide {
         Self: Sized;
 }
 
+//number of elements a uniformly subdivided set contains at least
+pub const ELEMENT_LIMIT: usize = 5000;
+
+//number of elements an adaptively subdivided set contains at most (give or take the pieces of one subdivision)
+pub const ADAPTIVE_ELEMENT_LIMIT: usize = 100 * ELEMENT_LIMIT;
+
+pub trait Flatness {
+    //distance of the element from the planar (or bilinear) shape spanned by its corners
+    fn flatness(&self) -> f32;
+}
+
 pub struct SubdivisionSet<T>
 where
     T: Subdivide,
@@ -27,10 +43,14 @@ where
     }
 
     pub fn subdivide(&mut self) {
+        self.subdivide_until(ELEMENT_LIMIT);
+    }
+
+    //subdivides all elements uniformly, finishes one subdivision run after limit is reached, possibly much larger
+    pub fn subdivide_until(&mut self, limit: usize) {
         if self.elements.len() == 0 {
             return;
         }
-        let limit = 5000; //finishes one subdivision run after limit is reached, possibly much larger
         while self.elements.len() < limit {
             let mut new_elements = Vec::<T>::new();
             for e in &self.elements {
@@ -40,17 +60,87 @@ where
             self.elements = new_elements;
         }
     }
+
+    //subdivides each element until its flatness is at most tolerance
+    pub fn subdivide_adaptive(&mut self, tolerance: f32)
+    where
+        T: Flatness,
+    {
+        self.subdivide_adaptive_until(tolerance, ADAPTIVE_ELEMENT_LIMIT);
+    }
+
+    /*
+        subdivides each element until its flatness is at most tolerance, but stops once the set has limit elements,
+        so a tiny tolerance can not produce more pieces than we can draw
+        an element is also not subdivided any more after MAX_DEPTH steps, even if it is not flat yet
+    */
+    pub fn subdivide_adaptive_until(&mut self, tolerance: f32, limit: usize)
+    where
+        T: Flatness,
+    {
+        let mut finished = Vec::<T>::new();
+        let mut count = self.elements.len();
+        let mut depth = 0;
+        while self.elements.len() > 0 {
+            let mut new_elements = Vec::<T>::new();
+            for e in self.elements.drain(..) {
+                if depth >= MAX_DEPTH || count >= limit || e.flatness() <= tolerance {
+                    finished.push(e);
+                } else {
+                    let mut pieces = e.subdivide();
+                    count += pieces.len() - 1;
+                    new_elements.append(&mut pieces);
+                }
+            }
+            self.elements = new_elements;
+            depth += 1;
+        }
+        self.elements = finished;
+    }
+
+    /*
+        subdivides all elements uniformly until each of them has a flatness of at most tolerance
+        unlike subdivide_adaptive all elements keep the same size, so that the edges of neighbouring elements match
+    */
+    pub fn subdivide_uniform_adaptive(&mut self, tolerance: f32)
+    where
+        T: Flatness,
+    {
+        self.subdivide_uniform_adaptive_until(tolerance, ADAPTIVE_ELEMENT_LIMIT);
+    }
+
+    //see subdivide_uniform_adaptive, stops before a subdivision step would make the set larger than limit
+    pub fn subdivide_uniform_adaptive_until(&mut self, tolerance: f32, limit: usize)
+    where
+        T: Flatness,
+    {
+        for _ in 0..MAX_DEPTH {
+            if self.elements.iter().all(|e| e.flatness() <= tolerance) {
+                return;
+            }
+            let elements: Vec<T> = self.elements.iter().flat_map(|e| e.subdivide()).collect();
+            if elements.len() > limit {
+                return;
+            }
+            self.elements = elements;
+        }
+    }
 }
 
 //what we really want to do is implement ToTriangle for a type that has
 //a vector part Vec3, but that would require additional generic parameter for these types
 //this means this block needs to be copied for BezierTriangles and BezierCurves
-impl<const N: usize, const M: usize> ToTriangle for SubdivisionSet<BezierRectangle<Vec3, N, M>>
-where
-    [(); (N + 1) * (M + 1)]:,
-    [(); math::triangular_number(N + 1)]:,
-    [(); math::triangular_number(M + 1)]:,
-{
+impl<const N: usize, const M: usize> ToTriangle for SubdivisionSet<BezierRectangle<Vec3, N, M>> {
+    fn to_triangles(&self) -> Vec<Triangle<Vec3>> {
+        let mut triangles = Vec::<Triangle<Vec3>>::new();
+        for e in &self.elements {
+            triangles.append(&mut e.to_triangles());
+        }
+        triangles
+    }
+}
+
+impl<const N: usize> ToTriangle for SubdivisionSet<BezierTriangle<Vec3, N>> {
     fn to_triangles(&self) -> Vec<Triangle<Vec3>> {
         let mut triangles = Vec::<Triangle<Vec3>>::new();
         for e in &self.elements {
@@ -59,3 +149,99 @@ where
         triangles
     }
 }
+
+impl ToTriangle for SubdivisionSet<DynBezierRectangle<Vec3>> {
+    fn to_triangles(&self) -> Vec<Triangle<Vec3>> {
+        let mut triangles = Vec::<Triangle<Vec3>>::new();
+        for e in &self.elements {
+            triangles.append(&mut e.to_triangles());
+        }
+        triangles
+    }
+}
+
+impl<const N: usize> ToSegments for SubdivisionSet<BezierCurve<Vec3, N>> {
+    fn to_segments(&self) -> Vec<Segment> {
+        let mut segments = Vec::<Segment>::new();
+        for e in &self.elements {
+            segments.append(&mut e.to_segments());
+        }
+        segments
+    }
+}
+
+impl ToSegments for SubdivisionSet<DynBezierCurve<Vec3>> {
+    fn to_segments(&self) -> Vec<Segment> {
+        let mut segments = Vec::<Segment>::new();
+        for e in &self.elements {
+            segments.append(&mut e.to_segments());
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Vec3 as Color;
+
+    //a biquadratic patch over the unit square with its center control point raised to height
+    fn patch(height: f32) -> BezierRectangle<Vec3, 2, 2> {
+        let mut pts = [Vec3::ZERO; 9];
+        for j in 0..3 {
+            for i in 0..3 {
+                pts[j * 3 + i] = Vec3::new(i as f32, j as f32, 0.);
+            }
+        }
+        pts[4].z = height;
+        BezierRectangle::new(pts, [Color::ONE; 4])
+    }
+
+    #[test]
+    fn adaptive_subdivision() {
+        let mut subdiv = SubdivisionSet::new();
+        subdiv.elements = vec![patch(4.)];
+        subdiv.subdivide_adaptive(0.01);
+        assert!(subdiv.elements.len() > 1);
+        assert!(subdiv.elements.iter().all(|e| e.flatness() <= 0.01));
+
+        //coarser tolerance needs fewer elements
+        let mut coarse = SubdivisionSet::new();
+        coarse.elements = vec![patch(4.)];
+        coarse.subdivide_adaptive(0.5);
+        assert!(coarse.elements.len() < subdiv.elements.len());
+    }
+
+    #[test]
+    fn uniform_adaptive_subdivision() {
+        let mut subdiv = SubdivisionSet::new();
+        subdiv.elements = vec![patch(4.), patch(0.)];
+        subdiv.subdivide_uniform_adaptive(0.01);
+        assert!(subdiv.elements.iter().all(|e| e.flatness() <= 0.01));
+        //the flat patch is subdivided as often as the curved one
+        let size = |e: &BezierRectangle<Vec3, 2, 2>| e.domain()[1] - e.domain()[0];
+        assert!(subdiv.elements.iter().all(|e| size(e) == size(&subdiv.elements[0])));
+    }
+
+    #[test]
+    fn adaptive_subdivision_limit() {
+        //a tolerance that can never be reached stops at the limit instead of at MAX_DEPTH
+        let mut subdiv = SubdivisionSet::new();
+        subdiv.elements = vec![patch(4.), patch(4.)];
+        subdiv.subdivide_adaptive_until(1e-30, 100);
+        assert!(subdiv.elements.len() >= 100 && subdiv.elements.len() < 100 + 3);
+
+        let mut uniform = SubdivisionSet::new();
+        uniform.elements = vec![patch(4.)];
+        uniform.subdivide_uniform_adaptive_until(1e-30, 100);
+        assert_eq!(uniform.elements.len(), 64);
+    }
+
+    #[test]
+    fn adaptive_subdivision_keeps_flat_elements() {
+        let mut subdiv = SubdivisionSet::new();
+        subdiv.elements = vec![patch(0.)];
+        subdiv.subdivide_adaptive(0.01);
+        assert_eq!(subdiv.elements.len(), 1);
+    }
+}
@@ -0,0 +1,316 @@
+use bevy::prelude::{Vec3, Vec4};
+use std::str::FromStr;
+
+use crate::error::ParseError;
+
+/*
+    The OOGL formats do not depend on the layout of the file, any whitespace separates values
+    and everything from a # up to the end of the line is a comment.
+    The only exception are optional values at the end of a line, eg. the color of an OFF face,
+    which are recognized by being on the same line as the values before them.
+    A token that starts with a letter is a keyword, eg. a header like CBEZ333, every other token is a value.
+    Braces and the < of an include are keywords as well, they are tokens of their own even without whitespace around them
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    //line and column start at 1
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Token {
+    pub fn is_keyword(&self) -> bool {
+        self.text.starts_with(|c: char| c.is_ascii_alphabetic()) || is_punctuation(&self.text)
+    }
+
+    //what describes the value in error messages
+    pub fn parse<F: FromStr>(&self, what: &str) -> Result<F, ParseError> {
+        self.text
+            .parse::<F>()
+            .map_err(|_| ParseError::new(self.line, self.column, format!("invalid {} '{}'", what, self.text)))
+    }
+}
+
+pub fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        for (column, text) in crate::util::tokens(line) {
+            for (offset, text) in split_punctuation(text) {
+                tokens.push(Token {
+                    text: text.to_string(),
+                    line: i + 1,
+                    column: column + offset,
+                });
+            }
+        }
+    }
+    tokens
+}
+
+fn is_punctuation(text: &str) -> bool {
+    matches!(text, "{" | "}" | "<")
+}
+
+//splits braces and a leading < off a whitespace separated token, eg. {LIST into { and LIST, with their offsets
+fn split_punctuation(text: &str) -> Vec<(usize, &str)> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if c == '{' || c == '}' || (c == '<' && i == 0) {
+            if start < i {
+                parts.push((start, &text[start..i]));
+            }
+            parts.push((i, &text[i..i + 1]));
+            start = i + 1;
+        }
+    }
+    if start < text.len() {
+        parts.push((start, &text[start..]));
+    }
+    parts
+}
+
+pub fn parse_all<F: FromStr>(tokens: &[Token], what: &str) -> Result<Vec<F>, ParseError> {
+    tokens.iter().map(|t| t.parse(what)).collect()
+}
+
+//parses groups of three tokens as vectors
+pub fn parse_vec3s(tokens: &[Token], what: &str) -> Result<Vec<Vec3>, ParseError> {
+    let values = parse_all::<f32>(tokens, what)?;
+    Ok(values.chunks_exact(3).map(|v| Vec3::new(v[0], v[1], v[2])).collect())
+}
+
+/*
+    whether colors are given as integers between 0 and 255 instead of floats between 0 and 1,
+    they are if all of their values are integers and at least one of them is larger than 1
+    this is decided for all colors of an object or OFF section together, not for each color,
+    so that eg. 1 0 0 is almost black next to 255 0 0, and not red
+    colors that only use the values 0 and 1 can not be told apart and are read as floats
+*/
+pub fn integer_colors<'a>(values: impl IntoIterator<Item = &'a Token>) -> bool {
+    let mut any_larger = false;
+    for t in values {
+        match t.text.parse::<u32>() {
+            Ok(value) => any_larger |= value > 1,
+            Err(_) => return false,
+        }
+    }
+    any_larger
+}
+
+//parses the colors of an object, each given by three or four values, as rgba with the scale of integer_colors
+pub fn parse_colors(colors: &[Vec<Token>]) -> Result<Vec<Vec4>, ParseError> {
+    let integers = integer_colors(colors.iter().flatten());
+    colors.iter().map(|values| parse_color(values, integers)).collect()
+}
+
+/*
+    parses a color given by three or four values as rgba, the alpha value defaults to opaque
+    the values are integers between 0 and 255 if integers is true, see integer_colors, and floats between 0 and 1 otherwise
+*/
+pub fn parse_color(tokens: &[Token], integers: bool) -> Result<Vec4, ParseError> {
+    let values = parse_all::<f32>(tokens, "color component")?;
+    let max = if integers { 255. } else { 1. };
+    let alpha = values.get(3).copied().unwrap_or(max);
+    Ok(Vec4::new(values[0], values[1], values[2], alpha) / max)
+}
+
+//parses a color given by three or four floats between 0 and 1 as rgba, as OBJ files have them, alpha defaults to opaque
+pub fn parse_float_color(tokens: &[Token]) -> Result<Vec4, ParseError> {
+    let values = parse_all::<f32>(tokens, "color component")?;
+    if let Some((t, _)) = tokens.iter().zip(&values).find(|(_, v)| !(0. ..=1.).contains(*v)) {
+        let reason = format!("color component {} is not between 0 and 1", t.text);
+        return Err(ParseError::new(t.line, t.column, reason));
+    }
+    let alpha = values.get(3).copied().unwrap_or(1.);
+    Ok(Vec4::new(values[0], values[1], values[2], alpha))
+}
+
+pub struct TokenStream {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl TokenStream {
+    pub fn new(input: &str) -> Self {
+        TokenStream {
+            tokens: tokenize(input),
+            position: 0,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    pub fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    //whether a value follows, ie. the current object or section is not finished yet
+    pub fn at_value(&self) -> bool {
+        self.peek().map_or(false, |t| !t.is_keyword())
+    }
+
+    /*
+        takes the next n values, what describes them in error messages
+        if the values end early because of a keyword or the end of the input, the values found are consumed as well
+        n often comes from the file, so the vector grows with the values found instead of being allocated up front
+    */
+    pub fn take(&mut self, n: usize, what: &str) -> Result<Vec<Token>, ParseError> {
+        let mut values = Vec::new();
+        while values.len() < n && self.at_value() {
+            values.push(self.next().unwrap());
+        }
+        if values.len() < n {
+            let (line, column) = self.current_position();
+            let reason = format!("missing {}, {} values expected but {} found", what, n, values.len());
+            return Err(ParseError::new(line, column, reason));
+        }
+        Ok(values)
+    }
+
+    //takes up to max values that are on the same line as the last token taken
+    pub fn take_rest_of_line(&mut self, max: usize) -> Vec<Token> {
+        let line = match self.position {
+            0 => return Vec::new(),
+            p => self.tokens[p - 1].line,
+        };
+        let mut values = Vec::new();
+        while values.len() < max && self.at_value() && self.peek().unwrap().line == line {
+            values.push(self.next().unwrap());
+        }
+        values
+    }
+
+    //the number of values on the line of the next token, if that token is the first one on its line
+    pub fn line_length(&self) -> Option<usize> {
+        let line = self.peek()?.line;
+        if self.position > 0 && self.tokens[self.position - 1].line == line {
+            return None;
+        }
+        Some(
+            self.tokens[self.position..]
+                .iter()
+                .take_while(|t| t.line == line && !t.is_keyword())
+                .count(),
+        )
+    }
+
+    //skips all values up to the next keyword
+    pub fn skip_values(&mut self) {
+        while self.at_value() {
+            self.position += 1;
+        }
+    }
+
+    //the position of the next token, or the position right after the last one at the end of the input
+    fn current_position(&self) -> (usize, usize) {
+        match (self.peek(), self.tokens.last()) {
+            (Some(t), _) => (t.line, t.column),
+            (None, Some(t)) => (t.line, t.column + t.text.len()),
+            (None, None) => (1, 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_and_layout() {
+        let mut tokens = TokenStream::new("CBEZ113 1 2 # comment 3\n  3 4#5\n\n# 6\n  CBEZ223");
+        assert!(tokens.next().unwrap().is_keyword());
+        let values = tokens.take(4, "coordinate").unwrap();
+        assert_eq!(parse_all::<f32>(&values, "coordinate").unwrap(), vec![1., 2., 3., 4.]);
+        assert_eq!((values[2].line, values[2].column), (2, 3));
+        assert!(!tokens.at_value());
+        assert_eq!(tokens.peek().unwrap().text, "CBEZ223");
+        assert_eq!((tokens.peek().unwrap().line, tokens.peek().unwrap().column), (5, 3));
+    }
+
+    #[test]
+    fn punctuation() {
+        let tokens = tokenize("{LIST {\n<a.off}} { INST");
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["{", "LIST", "{", "<", "a.off", "}", "}", "{", "INST"]);
+        assert_eq!((tokens[4].line, tokens[4].column), (2, 2));
+        assert!(tokens.iter().all(|t| t.is_keyword()));
+        let mut stream = TokenStream::new("1 2}");
+        assert_eq!(stream.take(2, "coordinate").unwrap().len(), 2);
+        assert!(!stream.at_value());
+    }
+
+    #[test]
+    fn missing_values() {
+        let mut tokens = TokenStream::new("1 2\n3 OFF");
+        let e = tokens.take(4, "coordinate").unwrap_err();
+        assert_eq!((e.line, e.column), (2, 3));
+        assert_eq!(e.reason, "missing coordinate, 4 values expected but 3 found");
+        assert_eq!(tokens.next().unwrap().text, "OFF");
+        let e = tokens.take(1, "vertex count").unwrap_err();
+        assert_eq!((e.line, e.column), (2, 6));
+
+        //a huge count is an error and not an allocation failure
+        let mut tokens = TokenStream::new("4000000000 0 1 2");
+        let n = tokens.next().unwrap().parse::<usize>("count").unwrap();
+        let e = tokens.take(3 * n, "coordinate").unwrap_err();
+        assert_eq!(e.reason, "missing coordinate, 12000000000 values expected but 3 found");
+    }
+
+    #[test]
+    fn rest_of_line() {
+        let mut tokens = TokenStream::new("1 2 3 0.5 0.5\n4 5 6\n7 8 9 1");
+        assert_eq!(tokens.line_length(), Some(5));
+        tokens.take(3, "coordinate").unwrap();
+        assert_eq!(tokens.line_length(), None);
+        assert_eq!(tokens.take_rest_of_line(4).len(), 2);
+        tokens.take(3, "coordinate").unwrap();
+        assert_eq!(tokens.take_rest_of_line(4).len(), 0);
+        let values = tokens.take(3, "coordinate").unwrap();
+        assert_eq!(parse_vec3s(&values, "coordinate").unwrap(), vec![Vec3::new(7., 8., 9.)]);
+        assert_eq!(tokens.take_rest_of_line(4)[0].text, "1");
+        assert_eq!(tokens.peek(), None);
+    }
+
+    #[test]
+    fn colors() {
+        let color = |input: &str| parse_colors(&[tokenize(input)]).map(|colors| colors[0]);
+        assert_eq!(color("1 0 0").unwrap(), Vec4::new(1., 0., 0., 1.));
+        assert_eq!(color("0.5 0 1 0.25").unwrap(), Vec4::new(0.5, 0., 1., 0.25));
+        assert_eq!(color("255 0 51").unwrap(), Vec4::new(1., 0., 0.2, 1.));
+        assert_eq!(color("255 0 0 51").unwrap(), Vec4::new(1., 0., 0., 0.2));
+        assert_eq!(color("1 0 0 1").unwrap(), Vec4::new(1., 0., 0., 1.));
+        assert!(color("1 0 x").is_err());
+
+        //the scale is chosen for all colors of an object, so 0 1 colors are integers next to larger integers
+        let colors = |input: &str| parse_colors(&input.lines().map(tokenize).collect::<Vec<_>>()).unwrap();
+        assert_eq!(colors("1 0 0\n255 255 255")[0], Vec4::new(1. / 255., 0., 0., 1.));
+        assert_eq!(colors("1 0 0\n0 1 0 1")[0], Vec4::new(1., 0., 0., 1.));
+        assert_eq!(colors("1 0 0\n0.5 0.5 0.5")[0], Vec4::new(1., 0., 0., 1.));
+        assert!(!integer_colors(&tokenize("0 1 1 0")));
+        assert!(integer_colors(&tokenize("0 1 2")));
+        assert!(!integer_colors(&tokenize("0 1 2.0")));
+
+        //float colors are never scaled
+        let float_color = |input: &str| parse_float_color(&tokenize(input));
+        assert_eq!(float_color("1 0 0").unwrap(), Vec4::new(1., 0., 0., 1.));
+        assert_eq!(float_color("0.5 0 1 0.25").unwrap(), Vec4::new(0.5, 0., 1., 0.25));
+        let e = float_color("0 2 0").unwrap_err();
+        assert_eq!((e.line, e.column), (1, 3));
+        assert_eq!(e.reason, "color component 2 is not between 0 and 1");
+        assert!(float_color("0 -0.5 0").is_err());
+        assert!(float_color("1 0 x").is_err());
+    }
+}
@@ -1,10 +1,15 @@
 use bevy::{
     prelude::*,
-    render::mesh::{Indices, PrimitiveTopology},
+    render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
 };
 use std::ops::{Add, Mul, Sub};
 use Vec3 as Color;
 
+use crate::error::ParseError;
+use crate::math::{self, Transform};
+use crate::tokens::{self, Token};
+use crate::util;
+
 pub trait ToTriangle {
     fn to_triangles(&self) -> Vec<Triangle<Vec3>>;
 }
@@ -16,7 +21,11 @@ where
 {
     points: [T; 3],
     colors: [Color; 3],
+    //opacity of each vertex color
+    alphas: [f32; 3],
     normals: [T; 3],
+    //texture coordinates of each vertex
+    uvs: [Vec2; 3],
 }
 
 impl<T> Triangle<T>
@@ -27,9 +36,41 @@ where
         Triangle {
             points: p,
             colors: c,
+            alphas: [1.; 3],
             normals: n,
+            uvs: [Vec2::ZERO; 3],
         }
     }
+
+    pub fn with_alphas(mut self, alphas: [f32; 3]) -> Self {
+        self.alphas = alphas;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [Vec2; 3]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    pub fn points(&self) -> [T; 3] {
+        self.points
+    }
+
+    pub fn normals(&self) -> [T; 3] {
+        self.normals
+    }
+
+    pub fn colors(&self) -> [Color; 3] {
+        self.colors
+    }
+
+    pub fn alphas(&self) -> [f32; 3] {
+        self.alphas
+    }
+
+    pub fn uvs(&self) -> [Vec2; 3] {
+        self.uvs
+    }
 }
 
 impl Triangle<Vec3> {
@@ -40,13 +81,16 @@ impl Triangle<Vec3> {
         Triangle {
             points: p,
             colors: c,
+            alphas: [1.; 3],
             normals: [n0, n1, n2],
+            uvs: [Vec2::ZERO; 3],
         }
     }
 }
 
 impl Triangle<Vec3> {
-    pub fn from_string(lines: [&String; 3], default_color: Color) -> Result<Self, String> {
+    //reads a triangle from its three vertex lines, the line of an error is the index of the vertex starting at 1
+    pub fn from_string(lines: [&String; 3], default_color: Color) -> Result<Self, ParseError> {
         let mut has_color = true;
         for pos in lines {
             if pos.split_whitespace().count() < 6 {
@@ -63,58 +107,29 @@ impl Triangle<Vec3> {
         }
     }
 
-    pub fn from_string_without_color(lines: [&String; 3], colors: [Color; 3]) -> Result<Self, String> {
+    pub fn from_string_without_color(lines: [&String; 3], colors: [Color; 3]) -> Result<Self, ParseError> {
         let mut positions = [Vec3::new(0., 0., 0.); 3];
-        let mut i = 0;
-        for string in lines.into_iter() {
-            let mut value = Vec3::new(0., 0., 0.);
-            let mut line_iter = string.split_whitespace();
-            if let Some(x) = line_iter.next() {
-                value.x = x.parse::<f32>().unwrap();
-            } else {
-                return Err(format!("missing vector element"));
-            }
-            if let Some(y) = line_iter.next() {
-                value.y = y.parse::<f32>().unwrap();
-            } else {
-                return Err(format!("missing vector element"));
-            }
-            if let Some(z) = line_iter.next() {
-                value.z = z.parse::<f32>().unwrap();
-            } else {
-                return Err(format!("missing vector element"));
-            }
-            positions[i] = value;
-            i += 1;
+        for (i, string) in lines.into_iter().enumerate() {
+            positions[i] = util::parse_vec3(string, i + 1, 0, "coordinate")?;
         }
         Ok(Triangle::new(positions, colors))
     }
 
-    pub fn from_string_with_color(values: [&String; 3]) -> Result<Self, String> {
-        let mut colors = [Color::new(0., 0., 0.); 3];
-        let mut i = 0;
-        for string in values {
-            let mut value = Vec3::new(0., 0., 0.);
-            let mut line_iter = string.split_whitespace().skip(3); //skip the positions only handle the colors now
-            if let Some(x) = line_iter.next() {
-                value.x = x.parse::<f32>().unwrap();
-            } else {
-                return Err(format!("missing vector element"));
+    pub fn from_string_with_color(values: [&String; 3]) -> Result<Self, ParseError> {
+        let mut color_values = Vec::with_capacity(3);
+        for (i, string) in values.into_iter().enumerate() {
+            //skip the positions only handle the colors now, they are rgb or rgba
+            let mut values: Vec<Token> = tokens::tokenize(string).into_iter().skip(3).take(4).collect();
+            for value in &mut values {
+                value.line = i + 1;
             }
-            if let Some(y) = line_iter.next() {
-                value.y = y.parse::<f32>().unwrap();
-            } else {
-                return Err(format!("missing vector element"));
-            }
-            if let Some(z) = line_iter.next() {
-                value.z = z.parse::<f32>().unwrap();
-            } else {
-                return Err(format!("missing vector element"));
-            }
-            colors[i] = value;
-            i += 1;
+            color_values.push(values);
         }
-        Self::from_string_without_color(values, colors)
+        //the three colors are either all integers or all floats
+        let parsed = tokens::parse_colors(&color_values)?;
+        let colors = [0, 1, 2].map(|i| parsed[i].truncate());
+        let alphas = [0, 1, 2].map(|i| parsed[i].w);
+        Ok(Self::from_string_without_color(values, colors)?.with_alphas(alphas))
     }
 }
 
@@ -137,8 +152,8 @@ impl Triangle<Vec3> {
                 indices.push(index);
                 positions.push(vec_to_array(t.points[i]));
                 normals.push(vec_to_array(t.normals[i]));
-                colors.push([t.colors[i].x, t.colors[i].y, t.colors[i].z, 1.]);
-                uvs.push([0., 0.]); //no texturing
+                colors.push([t.colors[i].x, t.colors[i].y, t.colors[i].z, t.alphas[i]]);
+                uvs.push([t.uvs[i].x, t.uvs[i].y]);
                 index += 1;
             }
         }
@@ -152,6 +167,24 @@ impl Triangle<Vec3> {
     }
 }
 
+impl Transform for Triangle<Vec3> {
+    fn transform(&mut self, m: Mat4) {
+        let normal_matrix = math::normal_matrix(m);
+        self.points = self.points.map(|p| m.project_point3(p));
+        self.normals = self
+            .normals
+            .map(|n| normal_matrix.transform_vector3(n).normalize_or_zero());
+    }
+}
+
+//whether any vertex color of the mesh is not fully opaque, such meshes have to be drawn with blending
+pub fn is_transparent(mesh: &Mesh) -> bool {
+    match mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
+        Some(VertexAttributeValues::Float32x4(colors)) => colors.iter().any(|c| c[3] < 1.),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -183,4 +216,33 @@ mod tests {
         assert_eq!(t.colors[1], Vec3::new(0.8, 0.8, 0.8));
         assert_eq!(t.colors[2], Vec3::new(0.8, 0.8, 0.8));
     }
+
+    #[test]
+    fn parse_triangle_with_rgba_colors() {
+        let a0 = format!("1 0 0 255 0 0 51");
+        let a1 = format!("0 1 0 0 255 0");
+        let a2 = format!("0 0 1 0 0 255 255");
+        let t = Triangle::from_string([&a0, &a1, &a2], Color::new(1., 1., 1.)).unwrap();
+        assert_eq!(t.colors[0], Vec3::new(1., 0., 0.));
+        assert_eq!(t.colors[1], Vec3::new(0., 1., 0.));
+        assert_eq!(t.alphas, [0.2, 1., 1.]);
+
+        //the colors of a triangle are either all integers or all floats
+        //so the 0s and 1s of the first two vertices are floats as well
+        let a0 = format!("1 0 0 1 0 0");
+        let a1 = format!("0 1 0 0 1 0");
+        let a2 = format!("0 0 1 0 0 1 0.5");
+        let t = Triangle::from_string([&a0, &a1, &a2], Color::new(1., 1., 1.)).unwrap();
+        assert_eq!(t.colors[0], Vec3::new(1., 0., 0.));
+        assert_eq!(t.colors[2], Vec3::new(0., 0., 1.));
+        assert_eq!(t.alphas, [1., 1., 0.5]);
+    }
+
+    #[test]
+    fn transparent_meshes() {
+        let t = || Triangle::new([Vec3::ZERO, Vec3::X, Vec3::Y], [Color::ONE; 3]);
+        assert!(!is_transparent(&Triangle::triangle_list_to_mesh(vec![t()])));
+        let mesh = Triangle::triangle_list_to_mesh(vec![t(), t().with_alphas([1., 0.5, 1.])]);
+        assert!(is_transparent(&mesh));
+    }
 }
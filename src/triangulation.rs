@@ -0,0 +1,125 @@
+use bevy::prelude::Vec3;
+
+/*
+    Splits a planar polygon, given by its points in order, into triangles
+    Returns the indices of the points of each triangle, the triangles keep the orientation of the polygon
+    Convex polygons are split into a fan around the first point, other polygons by ear clipping:
+    a corner whose triangle with its two neighbours is convex and contains no other point is cut off
+    until only a triangle is left
+*/
+pub fn triangulate(points: &[Vec3]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n < 3 {
+        return Vec::new();
+    }
+    let normal = polygon_normal(points);
+    if normal == Vec3::ZERO || is_convex(points, normal) {
+        return fan(&(0..n).collect::<Vec<usize>>());
+    }
+    ear_clipping(points, normal)
+}
+
+//the normal of a polygon by Newell's method, which also works for non-convex polygons
+pub fn polygon_normal(points: &[Vec3]) -> Vec3 {
+    let mut normal = Vec3::ZERO;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        normal += Vec3::new(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y),
+        );
+    }
+    normal.normalize_or_zero()
+}
+
+fn is_convex(points: &[Vec3], normal: Vec3) -> bool {
+    let n = points.len();
+    (0..n).all(|i| turn(points[i], points[(i + 1) % n], points[(i + 2) % n], normal) >= 0.)
+}
+
+//positive if the path a, b, c turns left around the normal
+fn turn(a: Vec3, b: Vec3, c: Vec3, normal: Vec3) -> f32 {
+    (b - a).cross(c - b).dot(normal)
+}
+
+fn fan(indices: &[usize]) -> Vec<[usize; 3]> {
+    (1..indices.len() - 1)
+        .map(|i| [indices[0], indices[i], indices[i + 1]])
+        .collect()
+}
+
+fn ear_clipping(points: &[Vec3], normal: Vec3) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::new();
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        match (0..len).find(|i| is_ear(points, &remaining, *i, normal)) {
+            Some(i) => {
+                triangles.push([remaining[(i + len - 1) % len], remaining[i], remaining[(i + 1) % len]]);
+                remaining.remove(i);
+            }
+            //only happens for self-intersecting polygons, the rest is covered as good as possible
+            None => break,
+        }
+    }
+    triangles.extend(fan(&remaining));
+    triangles
+}
+
+//whether the corner remaining[i] can be cut off
+fn is_ear(points: &[Vec3], remaining: &[usize], i: usize, normal: Vec3) -> bool {
+    let len = remaining.len();
+    let (prev, next) = ((i + len - 1) % len, (i + 1) % len);
+    let (a, b, c) = (points[remaining[prev]], points[remaining[i]], points[remaining[next]]);
+    if turn(a, b, c, normal) <= 0. {
+        return false;
+    }
+    remaining
+        .iter()
+        .enumerate()
+        .filter(|(k, _)| *k != prev && *k != i && *k != next)
+        .all(|(_, j)| !in_triangle(points[*j], a, b, c, normal))
+}
+
+//whether p lies inside the triangle a, b, c or on its boundary
+fn in_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3, normal: Vec3) -> bool {
+    (b - a).cross(p - a).dot(normal) >= 0.
+        && (c - b).cross(p - b).dot(normal) >= 0.
+        && (a - c).cross(p - c).dot(normal) >= 0.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(points: &[Vec3], triangles: &[[usize; 3]]) -> f32 {
+        let normal = polygon_normal(points);
+        triangles
+            .iter()
+            .map(|[a, b, c]| (points[*b] - points[*a]).cross(points[*c] - points[*a]).dot(normal) * 0.5)
+            .sum()
+    }
+
+    #[test]
+    fn convex_polygons() {
+        assert!(triangulate(&[Vec3::ZERO, Vec3::X]).is_empty());
+        let square = [Vec3::ZERO, Vec3::X, Vec3::new(1., 1., 0.), Vec3::Y];
+        assert_eq!(triangulate(&square), vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(polygon_normal(&square), Vec3::Z);
+    }
+
+    #[test]
+    fn concave_polygon() {
+        //an L shape in the yz plane, the fan around its first point would leave the polygon
+        let l = [(1., 1.), (0., 1.), (0., 0.), (2., 0.), (2., 2.), (1., 2.)].map(|(y, z)| Vec3::new(3., y, z));
+        let triangles = triangulate(&l);
+        assert_eq!(triangles.len(), 4);
+        assert!((area(&l, &triangles) - 3.).abs() < 1e-6);
+        //no triangle is flipped
+        for [a, b, c] in &triangles {
+            assert!(turn(l[*a], l[*b], l[*c], polygon_normal(&l)) > 0.);
+        }
+    }
+}
@@ -1,5 +1,8 @@
-use bevy::prelude::Color;
+use bevy::prelude::{Color, Vec3};
 use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::ParseError;
 
 pub fn file_exists(path: &str) -> Result<String, String> {
     if Path::new(path).exists() {
@@ -21,6 +24,15 @@ pub fn str_to_color(color: &str) -> Result<Color, String> {
     Ok(Color::rgb_u8(r, g, b))
 }
 
+//a number greater than 0, eg. a tolerance, nan and infinity are rejected as well
+pub fn positive_float(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(x) if x.is_finite() && x > 0. => Ok(x),
+        Ok(_) => Err("the value must be a finite number greater than 0".to_string()),
+        Err(_) => Err("parsing error".to_string()),
+    }
+}
+
 pub fn hex_char_val(c: char) -> Option<u8> {
     match c {
         '0' => Some(0),
@@ -52,6 +64,49 @@ pub fn hex_to_int(hex: &str) -> Option<u32> {
     Some(sum)
 }
 
+//the whitespace separated tokens of a line together with their column, starting at 1
+pub fn tokens(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push((s + 1, &line[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s + 1, &line[s..]));
+    }
+    tokens
+}
+
+//parses the token with the given index of a line, what describes the value in error messages
+pub fn parse_token<F: FromStr>(line: &str, line_number: usize, index: usize, what: &str) -> Result<F, ParseError> {
+    match tokens(line).get(index) {
+        Some((column, token)) => token
+            .parse::<F>()
+            .map_err(|_| ParseError::new(line_number, *column, format!("invalid {} '{}'", what, token))),
+        None => Err(ParseError::new(
+            line_number,
+            line.len() + 1,
+            format!("missing {}", what),
+        )),
+    }
+}
+
+//parses three floats starting at the token with index skip
+pub fn parse_vec3(line: &str, line_number: usize, skip: usize, what: &str) -> Result<Vec3, ParseError> {
+    Ok(Vec3::new(
+        parse_token(line, line_number, skip, what)?,
+        parse_token(line, line_number, skip + 1, what)?,
+        parse_token(line, line_number, skip + 2, what)?,
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -69,4 +124,29 @@ mod tests {
         assert_eq!(str_to_color("ff00ff").unwrap(), Color::rgb(1., 0., 1.));
         assert_eq!(str_to_color("#0000ff").unwrap(), Color::rgb(0., 0., 1.));
     }
+
+    #[test]
+    fn positive_floats() {
+        assert_eq!(positive_float("0.01").unwrap(), 0.01);
+        assert_eq!(positive_float("2").unwrap(), 2.);
+        for invalid in ["0", "-0.5", "NaN", "inf", "a"] {
+            assert!(positive_float(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_tokens() {
+        assert_eq!(tokens("  1 2.5\t-3 "), vec![(3, "1"), (5, "2.5"), (9, "-3")]);
+        assert_eq!(
+            parse_vec3("1 2 3 4", 1, 1, "coordinate").unwrap(),
+            Vec3::new(2., 3., 4.)
+        );
+        let e = parse_vec3("1 2.o 3", 7, 0, "coordinate").unwrap_err();
+        assert_eq!(
+            (e.line, e.column, e.reason.as_str()),
+            (7, 3, "invalid coordinate '2.o'")
+        );
+        let e = parse_token::<usize>("3 0 1", 1, 3, "vertex index").unwrap_err();
+        assert_eq!((e.column, e.reason.as_str()), (6, "missing vertex index"));
+    }
 }